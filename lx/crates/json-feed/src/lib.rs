@@ -10,12 +10,12 @@ mod v1_1;
 
 use std::{collections::HashMap, convert::TryFrom};
 
-use serde::{Deserialize, Serialize};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 use serde_json::Value;
 
 pub use v1_1::{AuthorOptions, Builder as JSONFeedBuilder, FeedItem, JSONFeed};
 
-#[derive(Debug, Deserialize, Serialize, PartialEq)]
+#[derive(Debug, PartialEq)]
 pub enum Version {
     /// The feed is [v1](https://jsonfeed.org/version/1).
     V1_0,
@@ -23,6 +23,29 @@ pub enum Version {
     V1_1,
 }
 
+impl Version {
+    /// The URL identifying the version, as it appears in the `version` field.
+    pub fn url(&self) -> &'static str {
+        match self {
+            Version::V1_0 => "https://jsonfeed.org/version/1",
+            Version::V1_1 => "https://jsonfeed.org/version/1.1",
+        }
+    }
+}
+
+impl Serialize for Version {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.url())
+    }
+}
+
+impl<'de> Deserialize<'de> for Version {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Version::try_from(s.as_str()).map_err(de::Error::custom)
+    }
+}
+
 impl TryFrom<&str> for Version {
     type Error = String;
 
@@ -109,4 +132,13 @@ mod tests {
             Err(format!("Bad JSON Feed `version` field: '{}'", bad))
         );
     }
+
+    #[test]
+    fn serializes_version_as_url() {
+        let serialized = serde_json::to_string(&Version::V1_1).unwrap();
+        assert_eq!(serialized, r#""https://jsonfeed.org/version/1.1""#);
+
+        let deserialized: Version = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(deserialized, Version::V1_1);
+    }
 }
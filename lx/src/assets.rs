@@ -0,0 +1,776 @@
+//! Files which go into the output as-is: everything in the directories the site
+//! config lists in `other_content.copy`, every file in `content` which is not
+//! an item or a data cascade file (e.g. `content/robots.txt`), and the
+//! stylesheets lx generates for syntax highlighting.
+//!
+//! The contents of a `copy` directory go at the root of the output, so that
+//! e.g. `_static/images/logo.png` is served at `/images/logo.png`, while files
+//! from `content` keep their path within it, like the items around them.
+//!
+//! With `other_content.fingerprint` set, stylesheets, scripts, fonts, and
+//! images from the `copy` directories (and the generated stylesheets) get the
+//! hash of their contents in their file names, e.g.
+//! `styles/style.3f9a1c2b.css`, so they can be cached forever. Layouts link to
+//! them by name with the `asset()` function, e.g. `{{ asset(path="light.css")
+//! }}`, and stylesheets' `url()`s are rewritten to match. Every build writes the
+//! mapping from names to fingerprinted paths to `asset-manifest.json`. Files
+//! from `content` keep their names, since the items beside them link to them
+//! directly, e.g. `![](photo.jpg)`.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use glob::MatchOptions;
+use lazy_static::lazy_static;
+use rayon::prelude::*;
+use regex::{Captures, Regex};
+use uuid::Uuid;
+
+use crate::build::files_under;
+use crate::config::Config;
+use crate::error::Error;
+
+/// Where the manifest goes in the output, if the site is fingerprinted.
+pub(crate) const MANIFEST: &str = "asset-manifest.json";
+
+/// The kinds of files which are fingerprinted: everything a page loads by
+/// itself, apart from `favicon.ico`, which browsers ask for by name.
+const FINGERPRINTED: &[&str] = &[
+    "css", "js", "mjs", "woff", "woff2", "ttf", "otf", "eot", "svg", "png", "jpg", "jpeg", "gif",
+    "webp", "avif",
+];
+
+lazy_static! {
+    /// A reference to another file in a stylesheet: either a `url()`, e.g.
+    /// `url("../fonts/serif.woff2?#iefix")`, or an `@import` of a bare string,
+    /// e.g. `@import "fonts.css"`.
+    static ref CSS_URL: Regex = Regex::new(concat!(
+        r#"url\(\s*['"]?(?P<url>[^'")?#\s]+)(?P<url_suffix>[^'")\s]*)['"]?\s*\)"#,
+        r#"|@import\s+['"](?P<import>[^'"?#]+)(?P<import_suffix>[^'"]*)['"]"#,
+    ))
+    .unwrap();
+}
+
+/// Every asset for a site, and where each ends up.
+pub(crate) struct Assets {
+    files: Vec<Asset>,
+    manifest: Arc<Manifest>,
+}
+
+/// A file to copy into the output.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Asset {
+    /// The file the asset comes from, or `None` if lx generates it.
+    pub(crate) source: Option<PathBuf>,
+    /// The asset's path within the output before fingerprinting, which is how
+    /// layouts refer to it, e.g. `images/logo.png`.
+    pub(crate) name: String,
+    pub(crate) output: PathBuf,
+    /// Whether to put the hash of the contents in the output file name.
+    fingerprint: bool,
+    /// What to write, if not simply a copy of the source: generated contents,
+    /// or a stylesheet with its `url()`s rewritten.
+    contents: Option<Vec<u8>>,
+}
+
+/// Where each asset is served from, by name.
+pub(crate) struct Manifest {
+    /// The URL path for each asset, relative to the root of the site, e.g.
+    /// `/images/logo.4c1d9e07.png` for `images/logo.png`.
+    paths: BTreeMap<String, String>,
+    fingerprinted: bool,
+    /// The assets which other assets refer to, e.g. the fonts in a stylesheet.
+    referenced: HashSet<String>,
+    /// The assets layouts have asked for since the last [`Manifest::reset`].
+    used: Mutex<HashSet<String>>,
+}
+
+impl Assets {
+    /// Find every asset for the site at `site_root`, plus the `generated` ones
+    /// (by name and contents), and fingerprint them if the site config asks.
+    /// Assets which cannot be read are left out and reported.
+    pub(crate) fn load(
+        site_root: &Path,
+        config: &Config,
+        generated: Vec<(String, String)>,
+    ) -> (Assets, Vec<Error>) {
+        let (mut files, mut errors) = match find(site_root, config) {
+            Ok(files) => (files, Vec::new()),
+            Err(e) => (Vec::new(), vec![e]),
+        };
+        files.extend(generated.into_iter().map(|(name, contents)| Asset {
+            source: None,
+            output: config.output.join(&name),
+            fingerprint: is_fingerprinted(&name),
+            name,
+            contents: Some(contents.into_bytes()),
+        }));
+
+        let fingerprinted = config.other_content.fingerprint;
+        let (files, referenced, fingerprint_errors) = if fingerprinted {
+            fingerprint(files, &config.output)
+        } else {
+            (files, HashSet::new(), Vec::new())
+        };
+        errors.extend(fingerprint_errors);
+
+        let paths = files
+            .iter()
+            .map(|asset| (asset.name.clone(), url_path(&config.output, &asset.output)))
+            .collect();
+        let manifest = Manifest {
+            paths,
+            fingerprinted,
+            referenced,
+            used: Mutex::new(HashSet::new()),
+        };
+
+        (
+            Assets {
+                files,
+                manifest: Arc::new(manifest),
+            },
+            errors,
+        )
+    }
+
+    pub(crate) fn files(&self) -> &[Asset] {
+        &self.files
+    }
+
+    pub(crate) fn manifest(&self) -> Arc<Manifest> {
+        Arc::clone(&self.manifest)
+    }
+
+    /// Where [`Assets::write`] puts the manifest, if it writes one.
+    pub(crate) fn manifest_path(&self, output_dir: &Path) -> Option<PathBuf> {
+        if self.manifest.fingerprinted {
+            Some(output_dir.join(MANIFEST))
+        } else {
+            None
+        }
+    }
+
+    /// Write every asset into the output (in parallel), skipping any which are
+    /// already there and unchanged, along with the manifest if the assets are
+    /// fingerprinted.
+    pub(crate) fn write(&self, output_dir: &Path) -> Vec<Error> {
+        let mut errors: Vec<Error> = self
+            .files
+            .par_iter()
+            .map(write)
+            .filter_map(Result::err)
+            .collect();
+
+        // Earlier builds' fingerprinted versions of the assets would otherwise
+        // pile up in the output.
+        let current: HashSet<&Path> = self
+            .files
+            .iter()
+            .map(|asset| asset.output.as_path())
+            .collect();
+        for asset in self.files.iter().filter(|asset| asset.fingerprint) {
+            for stale in stale_versions(asset, &current) {
+                if let Err(e) = std::fs::remove_file(&stale) {
+                    if e.kind() != std::io::ErrorKind::NotFound {
+                        errors.push(Error::io(&stale, e));
+                    }
+                }
+            }
+        }
+
+        if let Some(path) = self.manifest_path(output_dir) {
+            let written = std::fs::create_dir_all(output_dir)
+                .map_err(|e| Error::io(output_dir, e))
+                .and_then(|_| {
+                    serde_json::to_string_pretty(&self.manifest.paths).map_err(|e| Error::Output {
+                        path: path.clone(),
+                        message: e.to_string(),
+                    })
+                })
+                .and_then(|json| std::fs::write(&path, json).map_err(|e| Error::io(&path, e)));
+            if let Err(e) = written {
+                errors.push(e);
+            }
+        }
+
+        errors
+    }
+
+    /// A warning for every fingerprinted asset which nothing refers to by name,
+    /// since a link to it by its original path will not find it. Only
+    /// meaningful after rendering every layout since the last
+    /// [`Manifest::reset`].
+    pub(crate) fn unreferenced(&self) -> Vec<String> {
+        if !self.manifest.fingerprinted {
+            return Vec::new();
+        }
+
+        let used = self.manifest.used.lock().expect("never poisoned");
+        self.files
+            .iter()
+            .filter(|asset| asset.fingerprint)
+            .filter(|asset| {
+                !self.manifest.referenced.contains(&asset.name) && !used.contains(&asset.name)
+            })
+            .map(|asset| {
+                format!(
+                    "{0}: '{1}' is never referenced: it is fingerprinted, so layouts must link to it with `asset(path=\"{1}\")`",
+                    asset.describe(),
+                    asset.name
+                )
+            })
+            .collect()
+    }
+}
+
+impl Asset {
+    /// Where the asset comes from, for reporting errors.
+    pub(crate) fn describe(&self) -> String {
+        match &self.source {
+            Some(source) => source.display().to_string(),
+            None => format!("the generated '{}'", self.name),
+        }
+    }
+}
+
+impl Manifest {
+    /// The URL path for the asset `name`, e.g. for the `asset()` function.
+    pub(crate) fn resolve(&self, name: &str) -> Result<&str, String> {
+        let name = name.trim_start_matches('/');
+        match self.paths.get(name) {
+            Some(path) => {
+                self.used
+                    .lock()
+                    .expect("never poisoned")
+                    .insert(name.to_string());
+                Ok(path)
+            }
+            None => Err(format!(
+                "there is no asset '{}' (in the `other_content.copy` directories or `content`)",
+                name
+            )),
+        }
+    }
+
+    /// Forget which assets layouts have asked for, before rendering them all.
+    pub(crate) fn reset(&self) {
+        self.used.lock().expect("never poisoned").clear();
+    }
+}
+
+/// Every file to copy from the site at `site_root` into the output, leaving out
+/// anything matching one of the `other_content.exclude` globs.
+fn find(site_root: &Path, config: &Config) -> Result<Vec<Asset>, Error> {
+    let excluded = |path: &Path| {
+        let relative = path.strip_prefix(site_root).unwrap_or(path);
+        let options = MatchOptions {
+            require_literal_separator: true,
+            ..MatchOptions::new()
+        };
+        config
+            .other_content
+            .exclude
+            .iter()
+            .any(|pattern| pattern.matches_path_with(relative, options))
+    };
+    let asset = |dir: &Path, source: PathBuf, fingerprint: bool| {
+        let name = source.strip_prefix(dir).ok()?.to_str()?.replace('\\', "/");
+        Some(Asset {
+            output: config.output.join(&name),
+            fingerprint: fingerprint && is_fingerprinted(&name),
+            name,
+            source: Some(source),
+            contents: None,
+        })
+    };
+
+    let mut assets = Vec::new();
+    for dir in &config.other_content.copy {
+        let dir = site_root.join(dir);
+        assets.extend(
+            files_in(&dir)?
+                .into_iter()
+                .filter(|path| !excluded(path))
+                .filter_map(|source| asset(&dir, source, true)),
+        );
+    }
+
+    let content_dir = site_root.join("content");
+    assets.extend(
+        files_in(&content_dir)?
+            .into_iter()
+            .filter(|path| !is_content(path) && !excluded(path))
+            .filter_map(|source| asset(&content_dir, source, false)),
+    );
+
+    Ok(assets)
+}
+
+/// Whether lx itself reads the file at `path`, i.e. it is an item or a data
+/// cascade file.
+fn is_content(path: &Path) -> bool {
+    path.extension() == Some("md".as_ref()) || path.file_name() == Some("config.lx.yaml".as_ref())
+}
+
+fn files_in(dir: &Path) -> Result<Vec<PathBuf>, Error> {
+    Ok(files_under(dir, "**/*")?
+        .into_iter()
+        .filter(|path| path.is_file())
+        .collect())
+}
+
+fn is_fingerprinted(name: &str) -> bool {
+    let extension = Path::new(name)
+        .extension()
+        .and_then(|extension| extension.to_str());
+    match extension {
+        Some(extension) => FINGERPRINTED.contains(&extension.to_ascii_lowercase().as_str()),
+        None => false,
+    }
+}
+
+fn is_stylesheet(name: &str) -> bool {
+    name.to_ascii_lowercase().ends_with(".css")
+}
+
+/// Give every asset to fingerprint its hash in its output file name, and point
+/// every stylesheet at the fingerprinted paths. Returns the assets, the names
+/// of those which stylesheets refer to, and any assets which could not be read.
+fn fingerprint(assets: Vec<Asset>, output_dir: &Path) -> (Vec<Asset>, HashSet<String>, Vec<Error>) {
+    let (assets, errors): (Vec<Asset>, Vec<Error>) = assets
+        .into_par_iter()
+        .map(|mut asset| {
+            let is_read = asset.fingerprint || is_stylesheet(&asset.name);
+            if is_read && asset.contents.is_none() {
+                let source = asset
+                    .source
+                    .as_ref()
+                    .expect("only generated assets have contents");
+                asset.contents = Some(std::fs::read(source).map_err(|e| Error::io(source, e))?);
+            }
+            Ok(asset)
+        })
+        .partition_map(|result| match result {
+            Ok(asset) => rayon::iter::Either::Left(asset),
+            Err(e) => rayon::iter::Either::Right(e),
+        });
+
+    // Stylesheets' hashes depend on the fingerprinted paths of everything they
+    // refer to, so they go last. Those from `content` keep their names, but
+    // still refer to everything else by its fingerprinted path.
+    let (stylesheets, mut assets): (Vec<Asset>, Vec<Asset>) = assets
+        .into_iter()
+        .partition(|asset| is_stylesheet(&asset.name));
+
+    let mut paths: HashMap<String, String> = HashMap::new();
+    for asset in assets.iter_mut().filter(|asset| asset.fingerprint) {
+        let contents = asset.contents.take().expect("read above");
+        asset.output = output_dir.join(fingerprinted(&asset.name, &contents));
+        paths.insert(asset.name.clone(), url_path(output_dir, &asset.output));
+        // Plain copies stay copies, so unchanged files can be skipped cheaply.
+        if asset.source.is_none() {
+            asset.contents = Some(contents);
+        }
+    }
+
+    // Stylesheets can also refer to each other, e.g. with `@import`: rewrite
+    // each one only once all the others it refers to have their final paths,
+    // and any left in a cycle as they are.
+    let mut referenced = HashSet::new();
+    let mut pending = stylesheets;
+    while !pending.is_empty() {
+        let pending_names: HashSet<String> =
+            pending.iter().map(|asset| asset.name.clone()).collect();
+        let (ready, waiting): (Vec<Asset>, Vec<Asset>) = pending.into_iter().partition(|asset| {
+            css_references(asset)
+                .iter()
+                .all(|name| name == &asset.name || !pending_names.contains(name))
+        });
+        let (ready, waiting) = if ready.is_empty() {
+            (waiting, Vec::new())
+        } else {
+            (ready, waiting)
+        };
+
+        for mut asset in ready {
+            let contents = asset.contents.take().expect("read above");
+            let css = String::from_utf8_lossy(&contents);
+            let rewritten = CSS_URL.replace_all(&css, |captures: &Captures| {
+                let (url, suffix, is_import) = css_reference(captures);
+                match resolve(&asset.name, url)
+                    .and_then(|name| paths.get(&name).map(|path| (name, path)))
+                {
+                    Some((name, path)) if is_import => {
+                        referenced.insert(name);
+                        format!("@import \"{}{}\"", path, suffix)
+                    }
+                    Some((name, path)) => {
+                        referenced.insert(name);
+                        format!("url(\"{}{}\")", path, suffix)
+                    }
+                    None => captures[0].to_string(),
+                }
+            });
+            let rewritten = rewritten.into_owned().into_bytes();
+
+            if asset.fingerprint {
+                asset.output = output_dir.join(fingerprinted(&asset.name, &rewritten));
+            }
+            paths.insert(asset.name.clone(), url_path(output_dir, &asset.output));
+            if asset.source.is_none() || rewritten != contents {
+                asset.contents = Some(rewritten);
+            }
+            assets.push(asset);
+        }
+        pending = waiting;
+    }
+
+    (assets, referenced, errors)
+}
+
+/// The names of the assets a stylesheet refers to.
+fn css_references(stylesheet: &Asset) -> Vec<String> {
+    let css = String::from_utf8_lossy(stylesheet.contents.as_deref().unwrap_or_default());
+    CSS_URL
+        .captures_iter(&css)
+        .filter_map(|captures| resolve(&stylesheet.name, css_reference(&captures).0))
+        .collect()
+}
+
+/// The URL a [`CSS_URL`] match refers to, anything after it (a query or
+/// fragment), and whether it is an `@import`.
+fn css_reference<'c>(captures: &Captures<'c>) -> (&'c str, &'c str, bool) {
+    match (captures.name("url"), captures.name("import")) {
+        (Some(url), _) => (url.as_str(), suffix(captures, "url_suffix"), false),
+        (None, Some(import)) => (import.as_str(), suffix(captures, "import_suffix"), true),
+        (None, None) => unreachable!("every match has one or the other"),
+    }
+}
+
+fn suffix<'c>(captures: &Captures<'c>, name: &str) -> &'c str {
+    captures.name(name).map_or("", |suffix| suffix.as_str())
+}
+
+/// The name of the asset a `url` in the asset `from` refers to, if it refers to
+/// one on this site at all: either from the root of the site (`/fonts/a.woff`)
+/// or relative to `from` (`../fonts/a.woff`).
+fn resolve(from: &str, url: &str) -> Option<String> {
+    if url.contains(':') || url.starts_with("//") {
+        return None;
+    }
+
+    let path = if url.starts_with('/') {
+        PathBuf::from(url)
+    } else {
+        Path::new(from)
+            .parent()
+            .unwrap_or_else(|| Path::new(""))
+            .join(url)
+    };
+
+    let mut segments: Vec<&str> = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(segment) => segments.push(segment.to_str()?),
+            Component::ParentDir => {
+                segments.pop()?;
+            }
+            _ => {}
+        }
+    }
+    Some(segments.join("/"))
+}
+
+/// The name with the hash of the contents before its extension, e.g.
+/// `styles/style.3f9a1c2b.css` for `styles/style.css`.
+fn fingerprinted(name: &str, contents: &[u8]) -> String {
+    let hash = Uuid::new_v5(&Uuid::NAMESPACE_OID, contents)
+        .simple()
+        .to_string();
+    let hash = &hash[..8];
+    let path = Path::new(name);
+    match (path.file_stem(), path.extension()) {
+        (Some(stem), Some(extension)) => {
+            let file_name = format!(
+                "{}.{}.{}",
+                stem.to_string_lossy(),
+                hash,
+                extension.to_string_lossy()
+            );
+            match name.rfind('/') {
+                Some(index) => format!("{}/{}", &name[..index], file_name),
+                None => file_name,
+            }
+        }
+        _ => format!("{}.{}", name, hash),
+    }
+}
+
+/// The URL path for a file in the output directory, e.g. `/images/logo.png`.
+fn url_path(output_dir: &Path, output: &Path) -> String {
+    let relative = output.strip_prefix(output_dir).unwrap_or(output);
+    format!("/{}", relative.to_string_lossy().replace('\\', "/"))
+}
+
+/// Every file beside the `asset`'s output which is a fingerprinted version of
+/// it, e.g. `styles/style.0a1b2c3d.css` for `styles/style.css`, other than the
+/// `current` outputs.
+fn stale_versions(asset: &Asset, current: &HashSet<&Path>) -> Vec<PathBuf> {
+    let name = Path::new(&asset.name);
+    let (prefix, suffix) = match (name.file_stem(), name.extension(), name.file_name()) {
+        (Some(stem), Some(extension), _) => (
+            format!("{}.", stem.to_string_lossy()),
+            format!(".{}", extension.to_string_lossy()),
+        ),
+        (_, _, Some(file_name)) => (format!("{}.", file_name.to_string_lossy()), String::new()),
+        _ => return Vec::new(),
+    };
+    let is_version = |file_name: &str| {
+        file_name
+            .strip_prefix(&prefix)
+            .and_then(|rest| rest.strip_suffix(&suffix))
+            .is_some_and(|hash| hash.len() == 8 && hash.chars().all(|c| c.is_ascii_hexdigit()))
+    };
+
+    let dir = match asset
+        .output
+        .parent()
+        .and_then(|dir| std::fs::read_dir(dir).ok())
+    {
+        Some(dir) => dir,
+        None => return Vec::new(),
+    };
+    dir.filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|file_name| file_name.to_str())
+                .is_some_and(is_version)
+        })
+        .filter(|path| !current.contains(path.as_path()))
+        .collect()
+}
+
+fn write(asset: &Asset) -> Result<(), Error> {
+    let unchanged = match (&asset.contents, &asset.source) {
+        (Some(contents), _) => std::fs::read(&asset.output)
+            .map(|existing| &existing == contents)
+            .unwrap_or(false),
+        (None, Some(source)) => is_unchanged(source, &asset.output),
+        (None, None) => unreachable!("every asset has a source or contents"),
+    };
+    if unchanged {
+        return Ok(());
+    }
+
+    if let Some(containing_dir) = asset.output.parent() {
+        std::fs::create_dir_all(containing_dir).map_err(|e| Error::io(&asset.output, e))?;
+    }
+    match (&asset.contents, &asset.source) {
+        (Some(contents), _) => {
+            std::fs::write(&asset.output, contents).map_err(|e| Error::io(&asset.output, e))
+        }
+        (None, Some(source)) => std::fs::copy(source, &asset.output)
+            .map(|_| ())
+            .map_err(|e| Error::io(source, e)),
+        (None, None) => unreachable!("every asset has a source or contents"),
+    }
+}
+
+/// Whether `output` is already a copy of `source`. A copy at least as new as
+/// its source and the same size is assumed to be the same; otherwise (e.g.
+/// after a fresh checkout) only the contents can tell, which is still cheaper
+/// than rewriting the output and updating its modification time.
+fn is_unchanged(source: &Path, output: &Path) -> bool {
+    let (source_meta, output_meta) = match (source.metadata(), output.metadata()) {
+        (Ok(source_meta), Ok(output_meta)) => (source_meta, output_meta),
+        _ => return false,
+    };
+    if source_meta.len() != output_meta.len() {
+        return false;
+    }
+
+    match (source_meta.modified(), output_meta.modified()) {
+        (Ok(source_time), Ok(output_time)) if output_time >= source_time => true,
+        _ => match (std::fs::read(source), std::fs::read(output)) {
+            (Ok(source_contents), Ok(output_contents)) => source_contents == output_contents,
+            _ => false,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_site::TestSite;
+
+    /// The config for the tests' sites, and the files they start with.
+    const OTHER_CONTENT: &str =
+        "other_content: { copy: [\"_static\"], exclude: [\"**/*.njk\", \"_static/drafts/**\"] }";
+    const FILES: &[(&str, &str)] = &[
+        ("_static/images/logo.svg", "<svg/>"),
+        ("_static/drafts/logo.svg", "<svg/>"),
+        ("_static/fonts/serif.woff2", "font"),
+        ("content/robots.txt", "User-agent: *"),
+        ("content/journal/photo.jpg", "photo"),
+        ("content/journal/config.lx.yaml", "tags: [journal]"),
+        ("content/archive.njk", "{{ nope }}"),
+    ];
+
+    #[test]
+    fn finds_copy_dirs_and_other_content() {
+        let site = TestSite::new("assets-find", OTHER_CONTENT);
+        site.write(FILES);
+        let config = site.config();
+
+        let mut outputs: Vec<String> = find(&site.root, &config)
+            .expect("finds the assets")
+            .into_iter()
+            .map(|asset| {
+                asset
+                    .output
+                    .strip_prefix(&config.output)
+                    .unwrap()
+                    .display()
+                    .to_string()
+            })
+            .collect();
+        outputs.sort();
+        assert_eq!(
+            outputs,
+            vec![
+                "fonts/serif.woff2",
+                "images/logo.svg",
+                "journal/photo.jpg",
+                "robots.txt",
+            ]
+        );
+    }
+
+    #[test]
+    fn copies_only_what_changed() {
+        let site = TestSite::new("assets-copy", OTHER_CONTENT);
+        site.write(FILES);
+        let config = site.config();
+        let (assets, errors) = Assets::load(&site.root, &config, Vec::new());
+        assert!(errors.is_empty());
+
+        assert!(assets.write(&config.output).is_empty());
+        let robots = config.output.join("robots.txt");
+        assert_eq!(std::fs::read_to_string(&robots).unwrap(), "User-agent: *");
+
+        // A copy newer than its source and the same size counts as unchanged...
+        std::fs::write(&robots, "User-agent: ?").unwrap();
+        assert!(assets.write(&config.output).is_empty());
+        assert_eq!(std::fs::read_to_string(&robots).unwrap(), "User-agent: ?");
+
+        // ...but changed ones are copied again.
+        std::fs::write(site.root.join("content/robots.txt"), "User-agent: lx").unwrap();
+        assert!(assets.write(&config.output).is_empty());
+        assert_eq!(std::fs::read_to_string(&robots).unwrap(), "User-agent: lx");
+    }
+
+    #[test]
+    fn fingerprints_and_rewrites_stylesheets() {
+        let site = TestSite::new("assets-fingerprint", OTHER_CONTENT);
+        site.write(FILES);
+        let mut config = site.config();
+        config.other_content.fingerprint = true;
+        site.write(&[
+            (
+                "_static/styles/style.css",
+                "@import 'fonts.css';\nh1 { background: url(../images/logo.svg); }\na { background: url(data:image/png;base64,AA==); }",
+            ),
+            (
+                "_static/styles/fonts.css",
+                "@font-face { src: url(\"/fonts/serif.woff2?#iefix\") format(\"woff2\"); }",
+            ),
+            (
+                "content/journal/style.css",
+                "body { font: url(/fonts/serif.woff2); background: url(photo.jpg); }",
+            ),
+        ]);
+        let generated = vec![(String::from("light.css"), String::from("pre {}"))];
+
+        let (assets, errors) = Assets::load(&site.root, &config, generated);
+        assert!(errors.is_empty());
+        let manifest = assets.manifest();
+
+        let logo = manifest.resolve("images/logo.svg").unwrap().to_string();
+        assert_eq!(
+            logo,
+            format!("/{}", fingerprinted("images/logo.svg", b"<svg/>"))
+        );
+        assert_eq!(
+            manifest.resolve("/robots.txt"),
+            Ok("/robots.txt"),
+            "not fingerprinted"
+        );
+        assert!(manifest.resolve("images/nope.png").is_err());
+
+        let style = manifest.resolve("styles/style.css").unwrap().to_string();
+        let fonts = manifest.resolve("styles/fonts.css").unwrap().to_string();
+        assert!(style.starts_with("/styles/style.") && style.ends_with(".css"));
+        assert!(manifest
+            .resolve("light.css")
+            .unwrap()
+            .starts_with("/light."));
+
+        assert!(assets.write(&config.output).is_empty());
+        let read =
+            |url_path: &str| std::fs::read_to_string(config.output.join(&url_path[1..])).unwrap();
+        let css = read(&style);
+        assert!(css.contains(&format!("@import \"{}\"", fonts)), "{}", css);
+        assert!(css.contains(&format!("url(\"{}\")", logo)), "{}", css);
+        assert!(css.contains("url(data:image/png;base64,AA==)"), "{}", css);
+        let font = manifest.resolve("fonts/serif.woff2").unwrap().to_string();
+        assert!(read(&fonts).contains(&format!("url(\"{}?#iefix\")", font)));
+        let written: BTreeMap<String, String> =
+            serde_json::from_str(&read(&format!("/{}", MANIFEST))).unwrap();
+        assert_eq!(written["images/logo.svg"], logo);
+
+        // Items link to the files beside them directly, so those keep their
+        // names, though their stylesheets still refer to fingerprinted assets.
+        assert_eq!(
+            manifest.resolve("journal/photo.jpg"),
+            Ok("/journal/photo.jpg")
+        );
+        assert_eq!(
+            std::fs::read_to_string(config.output.join("journal/photo.jpg")).unwrap(),
+            "photo"
+        );
+        assert_eq!(
+            read("/journal/style.css"),
+            format!(
+                "body {{ font: url(\"{}\"); background: url(photo.jpg); }}",
+                font
+            )
+        );
+
+        // The stylesheets refer to the logo and the font, and the layouts asked
+        // for everything else above, until they are reset.
+        assert!(assets.unreferenced().is_empty());
+        manifest.reset();
+        let mut unreferenced = assets.unreferenced();
+        unreferenced.sort();
+        assert_eq!(unreferenced.len(), 2, "{:?}", unreferenced);
+        assert!(unreferenced[0].contains("'styles/style.css' is never referenced"));
+        assert!(unreferenced[1].contains("the generated 'light.css'"));
+
+        // Changing an asset leaves only its new version in the output.
+        site.write(&[("_static/images/logo.svg", "<svg></svg>")]);
+        let (changed, errors) = Assets::load(&site.root, &config, Vec::new());
+        assert!(errors.is_empty());
+        assert!(changed.write(&config.output).is_empty());
+        let new_logo = changed
+            .manifest()
+            .resolve("images/logo.svg")
+            .unwrap()
+            .to_string();
+        assert_ne!(new_logo, logo);
+        assert!(config.output.join(&new_logo[1..]).is_file());
+        assert!(!config.output.join(&logo[1..]).exists());
+    }
+}
@@ -20,6 +20,77 @@ pub(crate) enum Command {
     Build {
         /// The root of the site (if different from the current directory).
         site_directory: Option<PathBuf>,
+
+        /// Ignore the build cache, re-rendering all content from scratch.
+        #[clap(long)]
+        no_cache: bool,
+    },
+
+    /// Create a new site.
+    #[clap(name = "init")]
+    Init {
+        /// Where to create the site (if different from the current directory).
+        site_directory: Option<PathBuf>,
+
+        /// Overwrite the config, layout, and sample post of an existing site.
+        #[clap(long)]
+        force: bool,
+    },
+
+    /// Create a new item from one of the site's archetypes.
+    #[clap(name = "create")]
+    Create {
+        /// The kind of item, e.g. `notes`: the name of both the archetype in
+        /// `_data/archetypes` and the section of `content` to create it in.
+        kind: String,
+
+        /// The title of the item, from which its file name is derived. If not
+        /// set, the file is named for the current time.
+        #[clap(long)]
+        title: Option<String>,
+
+        /// The root of the site (if different from the current directory).
+        #[clap(long)]
+        site_directory: Option<PathBuf>,
+    },
+
+    /// Build the site, serve it locally, and rebuild and reload it on changes.
+    #[clap(name = "serve")]
+    Serve {
+        /// The root of the site (if different from the current directory).
+        site_directory: Option<PathBuf>,
+
+        /// The port to serve the site on.
+        #[clap(long, default_value_t = 8080)]
+        port: u16,
+
+        /// Ignore the build cache, re-rendering all content from scratch.
+        #[clap(long)]
+        no_cache: bool,
+    },
+
+    /// Write the syntax highlighting stylesheets for the themes in the site
+    /// config, to check in, so builds do not have to generate them.
+    #[clap(name = "themes")]
+    Themes {
+        /// The root of the site (if different from the current directory).
+        site_directory: Option<PathBuf>,
+
+        /// List the available themes instead: the defaults, plus any `.tmTheme`
+        /// files in the site's themes directory.
+        #[clap(long)]
+        list: bool,
+    },
+
+    /// Build the site, and rebuild whatever changes as it changes.
+    #[clap(name = "watch")]
+    Watch {
+        /// The root of the site (if different from the current directory).
+        site_directory: Option<PathBuf>,
+
+        /// Ignore the build cache, re-rendering all content from scratch.
+        #[clap(long)]
+        no_cache: bool,
     },
 }
 
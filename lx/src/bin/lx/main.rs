@@ -2,13 +2,63 @@
 
 mod cli;
 
+use lightning::Report;
+
 use crate::cli::Command;
 
-fn main() -> Result<(), String> {
+fn main() {
     let cwd = std::env::current_dir()
         .expect("Something is suuuuper borked: I cannot even get the current working directory!");
 
-    match Command::cli() {
-        Command::Build { site_directory } => lightning::build(site_directory.unwrap_or(cwd)),
+    let result =
+        match Command::cli() {
+            Command::Build {
+                site_directory,
+                no_cache,
+            } => lightning::build(site_directory.unwrap_or(cwd), !no_cache),
+            Command::Init {
+                site_directory,
+                force,
+            } => lightning::initializer::init(site_directory.unwrap_or(cwd), force)
+                .map_err(Report::from),
+            Command::Create {
+                kind,
+                title,
+                site_directory,
+            } => lightning::create::create(site_directory.unwrap_or(cwd), &kind, title)
+                .map(|path| println!("created {}", path.display()))
+                .map_err(Report::from),
+            Command::Serve {
+                site_directory,
+                port,
+                no_cache,
+            } => lightning::serve::serve(site_directory.unwrap_or(cwd), port, !no_cache)
+                .map_err(Report::from),
+            Command::Themes {
+                site_directory,
+                list: true,
+            } => lightning::themes::list(site_directory.unwrap_or(cwd))
+                .map(|names| names.iter().for_each(|name| println!("{}", name)))
+                .map_err(Report::from),
+            Command::Themes {
+                site_directory,
+                list: false,
+            } => lightning::themes::generate(site_directory.unwrap_or(cwd))
+                .map(|paths| {
+                    paths
+                        .iter()
+                        .for_each(|path| println!("wrote {}", path.display()))
+                })
+                .map_err(Report::from),
+            Command::Watch {
+                site_directory,
+                no_cache,
+            } => lightning::watch::watch(site_directory.unwrap_or(cwd), !no_cache)
+                .map_err(Report::from),
+        };
+
+    if let Err(report) = result {
+        eprintln!("{}", report);
+        std::process::exit(1);
     }
 }
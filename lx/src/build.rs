@@ -1,38 +1,140 @@
+use std::collections::{BTreeMap, BTreeSet};
 use std::path::{Path, PathBuf};
 
+use rayon::iter::Either;
 use rayon::prelude::*;
-use syntect::highlighting::ThemeSet;
-use syntect::html::{css_for_theme_with_class_style, ClassStyle};
 use syntect::parsing::SyntaxSet;
 
+use crate::assets::Assets;
+use crate::cache::{self, Cache};
 use crate::config::Config;
-use crate::page::{Page, Source};
-
-pub fn build(in_dir: PathBuf) -> Result<(), String> {
-    let in_dir = std::fs::canonicalize(in_dir).map_err(|e| e.to_string())?;
-    let config_path = in_dir.join(PathBuf::from("_data/config.json5"));
-    let config = Config::from_file(&config_path)?;
-
-    let syntax_set = load_syntaxes();
-
-    let SiteFiles {
-        // TODO: generate collections/taxonomies/whatever from configs
-        configs: _configs,
-        content,
-    } = get_files_to_load(&in_dir);
-    let ThemeSet { themes } = ThemeSet::load_defaults();
-
-    // TODO: generate these as a one-and-done with the themes I *actually* want,
-    // and build a tool that lets me trivially do that on command, but which I
-    // don't need to do unless I'm changing those themes! The output from that
-    // tool (which basically just does this) can just be checked into the repo
-    // and then updated only when needed.
-    let style = ClassStyle::Spaced;
-    let light = css_for_theme_with_class_style(&themes["InspiredGitHub"], style);
-    let dark = css_for_theme_with_class_style(&themes["base16-ocean.dark"], style);
-
-    std::fs::write(&config.output.join("light.css"), light).expect("can write output yo!");
-    std::fs::write(&config.output.join("dark.css"), dark).expect("can write output yo!");
+use crate::error::{Error, Report};
+use crate::feed::Feed;
+use crate::layout::Layouts;
+use crate::page::{metadata::cascade::Cascade, Page, Source};
+use crate::redirects::Rules;
+use crate::styles;
+use crate::syntaxes;
+use crate::taxonomy;
+use crate::themes;
+
+/// Build the site in `in_dir`. Unless `use_cache` is `false`, content which has
+/// not changed since the last build comes from the build cache (see
+/// [`crate::cache`]).
+///
+/// If more than one thing would write the same output file, nothing is written:
+/// which one won would depend on the order they happened to be written in.
+pub fn build(in_dir: PathBuf, use_cache: bool) -> Result<(), Report> {
+    let (site, mut errors) = Site::load(in_dir, use_cache, Mode::Release)?;
+    let collisions = site.collisions();
+    if collisions.is_empty() {
+        errors.extend(site.write_all());
+    } else {
+        errors.extend(collisions);
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(Report::new(errors))
+    }
+}
+
+/// Whether a build is for publishing the site or for working on it locally.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Mode {
+    /// A one-off build, e.g. for deploying: `lx build`.
+    Release,
+    /// A build which is kept up to date while working on the site: `lx watch`
+    /// and `lx serve`.
+    Development,
+}
+
+/// Everything loaded for a build. Kept around between builds in watch mode, so
+/// that a rebuild only has to redo the work for whatever changed.
+pub(crate) struct Site {
+    /// The root of the site, containing `_data`, `content`, etc.
+    pub(crate) root: PathBuf,
+    pub(crate) config: Config,
+    pub(crate) syntax_set: SyntaxSet,
+    pub(crate) layouts: Layouts,
+    pub(crate) cascade: Cascade,
+    pub(crate) pages: Vec<Page>,
+    /// The rules from the site's `_redirects` file, if it has one.
+    pub(crate) redirects: Option<Rules>,
+    /// Files to copy into the output as-is, and the syntax highlighting
+    /// stylesheets.
+    pub(crate) assets: Assets,
+    pub(crate) cache: Option<Cache>,
+    pub(crate) mode: Mode,
+}
+
+impl Site {
+    /// Load the config, layouts, and data for the site in `in_dir`, and parse
+    /// all of its content. Fails outright only if the site as a whole cannot be
+    /// loaded; errors in individual pages come back alongside the site.
+    pub(crate) fn load(
+        in_dir: PathBuf,
+        use_cache: bool,
+        mode: Mode,
+    ) -> Result<(Site, Vec<Error>), Error> {
+        let root = std::fs::canonicalize(&in_dir).map_err(|e| Error::io(&in_dir, e))?;
+        let config_path = root.join(PathBuf::from("_data/config.json5"));
+        let config = Config::from_file(&config_path)?;
+
+        let mut layouts = Layouts::load(&config.layouts.directory)?;
+
+        let SiteFiles { configs, content } = get_files_to_load(&root)?;
+        let cascade = Cascade::load(&configs, &config.cascade)?;
+        let (redirects, mut errors) = Rules::load(&root)?;
+        let (assets, asset_errors) = load_assets(&root, &config, mode);
+        errors.extend(asset_errors);
+        layouts.register_assets(assets.manifest());
+
+        // The cache is only an optimization, so a build without it is fine.
+        let cache = if use_cache {
+            syntaxes::files(&config)
+                .and_then(|syntax_paths| cache::fingerprint(&config_path, &configs, &syntax_paths))
+                .and_then(|fingerprint| Cache::open(&root, fingerprint))
+                .map_err(|e| eprintln!("not using the build cache: {}", e))
+                .ok()
+        } else {
+            None
+        };
+        let (syntax_set, syntax_errors) = syntaxes::load(&config, cache.as_ref());
+        errors.extend(syntax_errors);
+
+        let mut site = Site {
+            root,
+            config,
+            syntax_set,
+            layouts,
+            cascade,
+            pages: Vec::new(),
+            redirects,
+            assets,
+            cache,
+            mode,
+        };
+
+        let (pages, page_errors) = site.parse(content);
+        site.pages = pages;
+        errors.extend(page_errors);
+        if let Some(cache) = &site.cache {
+            cache.prune();
+        }
+
+        Ok((site, errors))
+    }
+
+    pub(crate) fn content_dir(&self) -> PathBuf {
+        self.root.join("content")
+    }
+
+    /// Every content file and data cascade file currently in the site.
+    pub(crate) fn files(&self) -> Result<SiteFiles, Error> {
+        get_files_to_load(&self.root)
+    }
 
     // NOTES:
     //
@@ -42,83 +144,259 @@ pub fn build(in_dir: PathBuf) -> Result<(), String> {
     // - At a minimum, there's a necessary choke point of collecting all of the
     //   rendered files so do further iteration before writing things out, b/c
     //   it's actually not possible to know what to render *without* that.
-    content
-        .into_par_iter()
-        .map(|path| {
-            std::fs::read_to_string(&path)
-                .map(|contents| Source {
-                    path: path.clone(),
-                    contents,
-                })
-                .map_err(|e| format!("{}: {}", path.display(), e))
-        })
-        .map(|result| {
-            result.and_then(|source| {
-                Page::new(&source, &in_dir.join("content"), &syntax_set, &config)
-                    .map_err(|e| format!("{}: {}", source.path.display(), e))
+    pub(crate) fn parse(&self, paths: Vec<PathBuf>) -> (Vec<Page>, Vec<Error>) {
+        let content_dir = self.content_dir();
+        paths
+            .into_par_iter()
+            .map(|path| {
+                std::fs::read_to_string(&path)
+                    .map(|contents| Source {
+                        path: path.clone(),
+                        contents,
+                    })
+                    .map_err(|e| Error::io(&path, e))
             })
-        })
-        .map(|result| {
-            result.and_then(|page| {
-                let path = page.path_from_root(&config.output).with_extension("html");
-                let containing_dir = path
-                    .parent()
-                    .ok_or_else(|| format!("{} should have a containing dir!", path.display()))?;
-
-                std::fs::create_dir_all(containing_dir)
-                    .map_err(|e| format!("{}: {}", path.display(), e.to_string()))?;
-
-                // TODO: replace with a templating engine!
-                std::fs::write(
-                    &path,
-                    format!(
-                        r#"<html>
-                            <head>
-                                <link rel="stylesheet" href="/light.css" media="(prefers-color-scheme: light)" />
-                                <link rel="stylesheet" href="/dark.css" media="(prefers-color-scheme: dark)" />
-                            </head>
-                            <body>
-                                {body}
-                            </body>
-                        </html>"#,
-                        body = page.contents
-                    ),
-                )
-                .map_err(|e| format!("{}: {}", path.display(), e))
+            .map(|result| result.and_then(|source| self.parse_page(&source, &content_dir)))
+            .partition_map(|result| match result {
+                Ok(page) => Either::Left(page),
+                Err(e) => Either::Right(e),
             })
-        })
-        .fold(
-            || Ok(()),
-            |so_far, result| match (so_far, result) {
-                (Ok(_), Ok(_)) => Ok(()),
-                (Err(s), Ok(_)) => Err(s),
-                (Ok(_), Err(e)) => Err(e),
-                (Err(s), Err(e)) => Err(s + &e),
-            },
-        )
-        .collect()
+    }
+
+    fn parse_page(&self, source: &Source, content_dir: &Path) -> Result<Page, Error> {
+        let cache = match &self.cache {
+            Some(cache) => cache,
+            None => {
+                return Page::new(
+                    source,
+                    content_dir,
+                    &self.syntax_set,
+                    &self.config,
+                    &self.cascade,
+                )
+            }
+        };
+
+        if let Some(entry) = cache.get(source) {
+            if let Ok(page) = Page::from_cache(
+                source,
+                content_dir,
+                &self.config,
+                entry.front_matter,
+                entry.contents,
+                entry.warnings,
+            ) {
+                return Ok(page);
+            }
+        }
+
+        let page = Page::new(
+            source,
+            content_dir,
+            &self.syntax_set,
+            &self.config,
+            &self.cascade,
+        )?;
+        cache.put(source, &page);
+        Ok(page)
+    }
+
+    /// Find the site's assets again, e.g. after some of them changed, and make
+    /// the new ones available to the layouts.
+    pub(crate) fn reload_assets(&mut self) -> Vec<Error> {
+        let (assets, errors) = load_assets(&self.root, &self.config, self.mode);
+        self.layouts.register_assets(assets.manifest());
+        self.assets = assets;
+        errors
+    }
+
+    /// Write the whole site: every page and asset, every feed, taxonomy, and
+    /// archive page, and the redirects.
+    pub(crate) fn write_all(&self) -> Vec<Error> {
+        let output = &self.config.output;
+        if let Err(e) = std::fs::create_dir_all(output) {
+            return vec![Error::io(output, e)];
+        }
+
+        let manifest = self.assets.manifest();
+        manifest.reset();
+
+        let pages: Vec<&Page> = self.pages.iter().collect();
+        let (mut errors, asset_errors) = rayon::join(
+            || self.write_pages(&pages),
+            || self.assets.write(&self.config.output),
+        );
+        errors.extend(asset_errors);
+        errors.extend(self.write_collections(None));
+        errors.extend(self.write_redirects());
+
+        // Only now has every layout had its chance to ask for every asset.
+        for warning in self.assets.unreferenced() {
+            eprintln!("warning: {}", warning);
+        }
+        errors
+    }
+
+    /// Every output file which more than one item or generated page would
+    /// write, e.g. for `Foo Bar.md` and `foo-bar.md`, or an item whose
+    /// `permalink` is the same as a taxonomy's.
+    pub(crate) fn collisions(&self) -> Vec<Error> {
+        self.outputs()
+            .into_iter()
+            .filter(|(_, sources)| sources.len() > 1)
+            .map(|(path, sources)| Error::Collision { path, sources })
+            .collect()
+    }
+
+    /// Every output file, and everything which would write it.
+    fn outputs(&self) -> BTreeMap<PathBuf, Vec<String>> {
+        let mut outputs: BTreeMap<PathBuf, Vec<String>> = BTreeMap::new();
+        let mut add = |path: PathBuf, source: String| {
+            outputs.entry(path).or_default().push(source);
+        };
+
+        for page in &self.pages {
+            add(
+                page.output_path(&self.config),
+                page.source_path.display().to_string(),
+            );
+        }
+        for asset in self.assets.files() {
+            add(asset.output.clone(), asset.describe());
+        }
+        if let Some(path) = self.assets.manifest_path(&self.config.output) {
+            add(path, String::from("the asset manifest"));
+        }
+        for feed in Feed::all_for_site(&self.config, &self.pages) {
+            for path in feed.output_paths(&self.config.output) {
+                add(path, feed.describe());
+            }
+        }
+        // Any errors in the taxonomies are reported when writing them.
+        let (taxonomies, _) = taxonomy::assign(&self.config, &self.pages);
+        for terms in &taxonomies {
+            for path in terms.output_paths(&self.config) {
+                add(path, terms.describe());
+            }
+        }
+        let (archives, _) = taxonomy::archive::assign(&self.config, &self.pages);
+        for archive in &archives {
+            for path in archive.output_paths(&self.config) {
+                add(path, archive.describe());
+            }
+        }
+
+        outputs
+    }
+
+    /// Check the redirects (from the redirects file and from every page's
+    /// `aliases`) against everything else in the site, and write them into the
+    /// output.
+    pub(crate) fn write_redirects(&self) -> Vec<Error> {
+        let redirects = Rules::for_site(self.redirects.as_ref(), &self.pages, &self.config);
+        if redirects.is_empty() {
+            return Vec::new();
+        }
+
+        let outputs: BTreeSet<PathBuf> = self.outputs().into_keys().collect();
+        let (mut errors, warnings) = redirects.validate(&self.config.output, &outputs);
+        for warning in warnings {
+            eprintln!("warning: {}", warning);
+        }
+        if let Err(e) = redirects.write(&self.config, &outputs) {
+            errors.push(e);
+        }
+        errors
+    }
+
+    pub(crate) fn write_pages(&self, pages: &[&Page]) -> Vec<Error> {
+        pages
+            .par_iter()
+            .map(|page| write_page(page, &self.layouts, &self.config))
+            .filter_map(Result::err)
+            .collect()
+    }
+
+    /// Write the feeds, taxonomy pages, and archive pages. If `changed` is set,
+    /// write only those which include (or used to include) any of the `changed`
+    /// pages, which should have both the old and new versions of every page
+    /// which changed.
+    pub(crate) fn write_collections(&self, changed: Option<&[&Page]>) -> Vec<Error> {
+        let mut errors = Vec::new();
+
+        errors.extend(
+            Feed::all_for_site(&self.config, &self.pages)
+                .iter()
+                .filter(|feed| match changed {
+                    Some(changed) => changed.iter().any(|page| feed.covers(page)),
+                    None => true,
+                })
+                .map(|feed| feed.write(&self.config.output))
+                .filter_map(Result::err),
+        );
+
+        let (taxonomies, taxonomy_errors) = taxonomy::assign(&self.config, &self.pages);
+        errors.extend(taxonomy_errors);
+        errors.extend(
+            taxonomies
+                .iter()
+                .map(|terms| terms.write(&self.layouts, &self.config, changed))
+                .filter_map(Result::err),
+        );
+
+        let (archives, archive_errors) = taxonomy::archive::assign(&self.config, &self.pages);
+        errors.extend(archive_errors);
+        errors.extend(
+            archives
+                .iter()
+                .map(|archive| archive.write(&self.layouts, &self.config, changed))
+                .filter_map(Result::err),
+        );
+
+        errors
+    }
 }
 
-struct SiteFiles {
-    configs: Vec<PathBuf>,
-    content: Vec<PathBuf>,
+fn write_page(page: &Page, layouts: &Layouts, config: &Config) -> Result<(), Error> {
+    let path = page.output_path(config);
+    if let Some(containing_dir) = path.parent() {
+        std::fs::create_dir_all(containing_dir).map_err(|e| Error::io(&path, e))?;
+    }
+
+    let rendered = layouts.render_page(page, config)?;
+    std::fs::write(&path, rendered).map_err(|e| Error::io(&path, e))
+}
+
+pub(crate) struct SiteFiles {
+    pub(crate) configs: Vec<PathBuf>,
+    pub(crate) content: Vec<PathBuf>,
 }
 
-fn get_files_to_load(in_dir: &Path) -> SiteFiles {
+fn get_files_to_load(in_dir: &Path) -> Result<SiteFiles, Error> {
     let content_dir = in_dir.join("content");
-    let dir_for_glob = content_dir.display();
+    Ok(SiteFiles {
+        configs: files_under(&content_dir, "**/config.lx.yaml")?,
+        content: files_under(&content_dir, "**/*.md")?,
+    })
+}
 
-    SiteFiles {
-        configs: get_files(format!("{}/**/config.lx.yaml", dir_for_glob)),
-        content: get_files(format!("{}/**/*.md", dir_for_glob)),
-    }
+/// Every path under `dir` matching the glob `pattern`, e.g. `**/*.md`.
+pub(crate) fn files_under(dir: &Path, pattern: &str) -> Result<Vec<PathBuf>, Error> {
+    // The directory may contain characters which mean something in a glob.
+    let dir_for_glob = glob::Pattern::escape(&dir.to_string_lossy());
+    get_files(format!("{}/{}", dir_for_glob, pattern))
 }
 
-fn get_files<S: AsRef<str>>(glob_src: S) -> Vec<PathBuf> {
+fn get_files<S: AsRef<str>>(glob_src: S) -> Result<Vec<PathBuf>, Error> {
     let src = glob_src.as_ref();
-    let (ok_files, err_files): (Vec<PathBuf>, Vec<String>) = glob::glob(src)
-        .unwrap_or_else(|_| panic!("bad glob: '{}'", src))
-        .fold((vec![], vec![]), |(mut good, mut bad), result| {
+    let paths = glob::glob(src).map_err(|e| {
+        Error::io(
+            Path::new(src),
+            std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("bad glob: {}", e)),
+        )
+    })?;
+    let (ok_files, err_files): (Vec<PathBuf>, Vec<String>) =
+        paths.fold((vec![], vec![]), |(mut good, mut bad), result| {
             match result {
                 Ok(path) => good.push(path),
                 Err(e) => bad.push(e.to_string()),
@@ -131,18 +409,180 @@ fn get_files<S: AsRef<str>>(glob_src: S) -> Vec<PathBuf> {
         eprintln!("problem with {}", err);
     }
 
-    ok_files
+    Ok(ok_files)
 }
 
-fn load_syntaxes() -> SyntaxSet {
-    // let mut extra_syntaxes_dir = std::env::current_dir().map_err(|e| format!("{}", e))?;
-    // extra_syntaxes_dir.push("syntaxes");
+/// Find the site's assets, including the generated ones: the syntax highlighting
+/// stylesheets and the compiled Sass stylesheets.
+fn load_assets(root: &Path, config: &Config, mode: Mode) -> (Assets, Vec<Error>) {
+    let (mut generated, mut errors) = styles::compile(root, config, mode);
+    match themes::stylesheets(root, config) {
+        Ok(stylesheets) => generated.extend(stylesheets),
+        Err(e) => errors.push(e),
+    }
+    let (assets, asset_errors) = Assets::load(root, config, generated);
+    errors.extend(asset_errors);
+    (assets, errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_site::TestSite;
+
+    #[test]
+    fn bad_items_do_not_stop_the_build() {
+        // Brackets mean something in a glob, so make sure they do not here.
+        let test_site = TestSite::new("build-[brackets]", "");
+        let site = test_site.root.clone();
 
-    let syntax_builder = SyntaxSet::load_defaults_newlines().into_builder();
-    // let mut syntax_builder = SyntaxSet::load_defaults_newlines().into_builder();
-    // syntax_builder
-    //     .add_from_folder(&extra_syntaxes_dir, false)
-    //     .map_err(|e| format!("could not load {}: {}", &extra_syntaxes_dir.display(), e))?;
+        let item = "---\ntitle: Unsluggable\ndate: 2020-01-01T10:30:00-07:00\n---\nHi!";
+        std::fs::write(site.join("content/!!!.md"), item).expect("writes item");
 
-    syntax_builder.build()
+        let report = build(site.clone(), false).expect_err("reports the bad item");
+        assert_eq!(report.errors().len(), 1);
+        assert!(matches!(report.errors()[0], Error::Slug { .. }));
+        assert!(site.join("public/hello-world.html").exists());
+        assert!(site.join("public/light.css").exists());
+    }
+
+    #[test]
+    fn colliding_outputs_fail_the_build() {
+        let test_site = TestSite::new(
+            "build-collision",
+            "taxonomies: [{ name: \"tags\", type: \"multiple\", templates: { item: \"base.html\" } }]",
+        );
+        test_site.write(&[
+            (
+                "content/Hello World.md",
+                "---\ntitle: Hello again\n---\nHi!",
+            ),
+            (
+                "content/rust.md",
+                "---\ntitle: Rust\npermalink: tags/rust\ntags: [Rust]\n---\nHi!",
+            ),
+        ]);
+        let site = test_site.root.clone();
+
+        let report = build(site.clone(), false).expect_err("reports the collisions");
+        let mut collisions: Vec<(String, usize)> = report
+            .errors()
+            .iter()
+            .map(|error| match error {
+                Error::Collision { path, sources } => (
+                    path.strip_prefix(site.join("public"))
+                        .unwrap()
+                        .display()
+                        .to_string(),
+                    sources.len(),
+                ),
+                other => panic!("expected a collision, got {:?}", other),
+            })
+            .collect();
+        collisions.sort();
+        assert_eq!(
+            collisions,
+            vec![
+                (String::from("hello-world.html"), 2),
+                (String::from("tags/rust.html"), 2)
+            ]
+        );
+        assert!(report.to_string().contains("the 'tags' taxonomy"));
+        assert!(
+            !site.join("public/hello-world.html").exists(),
+            "writes nothing"
+        );
+    }
+
+    #[test]
+    fn aliases_redirect_to_their_pages() {
+        let test_site = TestSite::new("build-alias", "");
+        let site = test_site.root.clone();
+
+        std::fs::write(
+            site.join("content/moved.md"),
+            "---\ntitle: Moved\naliases: [/notes/0634/, old-name]\n---\nHi!",
+        )
+        .unwrap();
+        build(site.clone(), false).expect("builds the site");
+
+        let redirects = std::fs::read_to_string(site.join("public/_redirects")).unwrap();
+        assert_eq!(redirects, "/notes/0634/ /moved 301\n/old-name /moved 301\n");
+        let stub = std::fs::read_to_string(site.join("public/notes/0634/index.html")).unwrap();
+        assert!(stub.contains("<link rel=\"canonical\" href=\"https://example.com/moved\">"));
+        assert!(site.join("public/old-name/index.html").exists());
+
+        std::fs::write(
+            site.join("content/moved.md"),
+            "---\ntitle: Moved\naliases: [/hello-world]\n---\nHi!",
+        )
+        .unwrap();
+        let report = build(site.clone(), false).expect_err("reports the alias");
+        assert_eq!(report.errors().len(), 1);
+        assert!(matches!(report.errors()[0], Error::Redirect { .. }));
+        let page = std::fs::read_to_string(site.join("public/hello-world.html")).unwrap();
+        assert!(!page.contains("http-equiv"), "does not replace the page");
+    }
+
+    #[test]
+    fn the_sample_redirects_fit_the_sample_site() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/sympolymathesy");
+        let (site, errors) = Site::load(root, false, Mode::Release).expect("loads the site");
+        assert!(errors.is_empty(), "{:?}", errors);
+
+        let redirects = Rules::for_site(site.redirects.as_ref(), &site.pages, &site.config);
+        assert!(!redirects.is_empty());
+        let outputs: BTreeSet<PathBuf> = site.outputs().into_keys().collect();
+        let (errors, warnings) = redirects.validate(&site.config.output, &outputs);
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert!(
+            warnings
+                .iter()
+                .all(|warning| warning.ends_with("which does not exist")),
+            "{:?}",
+            warnings
+        );
+    }
+
+    #[test]
+    fn permalink_patterns_and_pretty_urls() {
+        let test_site = TestSite::new(
+            "build-permalink",
+            "permalinks: { pretty: true, sections: { journal: \"/{year}/{month}/{slug}/\" } }",
+        );
+        test_site.write(&[(
+            "content/journal/First Entry.md",
+            "---\ntitle: First Entry\ndate: 2020-03-04T06:34:00-07:00\n---\nHi!",
+        )]);
+        let site = test_site.root.clone();
+
+        let (site_data, errors) =
+            Site::load(site.clone(), false, Mode::Release).expect("loads the site");
+        assert!(errors.is_empty(), "{:?}", errors);
+        let entry = site_data
+            .pages
+            .iter()
+            .find(|page| page.metadata.slug == "2020/03/first-entry")
+            .expect("uses the journal pattern");
+        assert_eq!(
+            entry.url(&site_data.config),
+            "https://example.com/2020/03/first-entry/"
+        );
+
+        assert!(site_data.write_all().is_empty());
+        assert!(site.join("public/2020/03/first-entry/index.html").exists());
+        assert!(site.join("public/hello-world/index.html").exists());
+        assert!(!site.join("public/hello-world.html").exists());
+    }
+
+    #[test]
+    fn bad_globs_are_errors() {
+        match files_under(Path::new("/site/content"), "[") {
+            Err(Error::Io { path, source }) => {
+                assert_eq!(path, Path::new("/site/content/["));
+                assert_eq!(source.kind(), std::io::ErrorKind::InvalidInput);
+            }
+            other => panic!("expected an error, got {:?}", other),
+        }
+    }
 }
@@ -0,0 +1,370 @@
+//! A persistent cache of parsed and rendered content, so that a build only has
+//! to render Markdown (and highlight code) for the content which changed since
+//! the previous build.
+//!
+//! The cache lives in `.lx-cache/` at the root of the site. Each entry is keyed
+//! on the path and contents of a source file, and holds its front matter (as
+//! resolved against the data cascade), its rendered HTML, and any warnings
+//! rendering it produced. The cache as a whole is only good for the format it
+//! was written in (see [`FORMAT`]), and the site config, data cascade, and
+//! syntax definitions it was built with: if any of those change, it starts
+//! over from scratch.
+//!
+//! The cache is strictly an optimization, so failing to read or write it is
+//! never an error: the build just does the work instead.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde_derive::{Deserialize, Serialize};
+use serde_yaml::Mapping;
+use syntect::parsing::SyntaxSet;
+use uuid::Uuid;
+
+use crate::error::Error;
+use crate::page::{Page, PostProcessed, Source};
+
+/// The directory, relative to the site root, where the cache lives.
+pub(crate) const DIR: &str = ".lx-cache";
+
+/// The format of the cache. Bump it whenever what the cache stores, or how lx
+/// builds pages from what it stores (including the default syntax definitions
+/// syntect ships), changes.
+const FORMAT: u32 = 1;
+
+const MANIFEST: &str = "manifest.json";
+
+/// Where the combined syntax definitions go (see [`crate::syntaxes`]).
+const SYNTAXES: &str = "syntaxes.packdump";
+
+pub(crate) struct Cache {
+    dir: PathBuf,
+    /// The key of the entry last read or written for each source path since
+    /// the cache was opened, so that entries for content which no longer
+    /// exists (or has changed since) can be pruned.
+    used: Mutex<HashMap<PathBuf, String>>,
+}
+
+/// What the cache was built with.
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+struct Manifest {
+    format: u32,
+    fingerprint: String,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct Entry {
+    pub(crate) front_matter: Mapping,
+    pub(crate) contents: PostProcessed,
+    pub(crate) warnings: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct EntryRef<'p> {
+    front_matter: &'p Mapping,
+    contents: &'p PostProcessed,
+    warnings: &'p [String],
+}
+
+impl Cache {
+    /// Open the cache for the site at `site_root`, clearing it out first if it
+    /// was written in a different format or with a different `fingerprint`
+    /// (see [`fingerprint`]).
+    pub(crate) fn open(site_root: &Path, fingerprint: String) -> Result<Cache, Error> {
+        let dir = site_root.join(DIR);
+        let manifest = Manifest {
+            format: FORMAT,
+            fingerprint,
+        };
+
+        let manifest_path = dir.join(MANIFEST);
+        let existing = std::fs::read_to_string(&manifest_path)
+            .ok()
+            .and_then(|contents| serde_json::from_str::<Manifest>(&contents).ok());
+
+        if existing.as_ref() != Some(&manifest) {
+            if dir.exists() {
+                std::fs::remove_dir_all(&dir).map_err(|e| Error::io(&dir, e))?;
+            }
+            std::fs::create_dir_all(&dir).map_err(|e| Error::io(&dir, e))?;
+
+            let contents = serde_json::to_string(&manifest).map_err(|e| Error::Output {
+                path: manifest_path.clone(),
+                message: e.to_string(),
+            })?;
+            std::fs::write(&manifest_path, contents).map_err(|e| Error::io(&manifest_path, e))?;
+        }
+
+        Ok(Cache {
+            dir,
+            used: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// The cached front matter and contents for `source`, if there are any.
+    pub(crate) fn get(&self, source: &Source) -> Option<Entry> {
+        let key = key(source);
+        let contents = std::fs::read_to_string(self.entry_path(&key)).ok()?;
+        let entry = serde_json::from_str(&contents).ok()?;
+        self.use_key(&source.path, key);
+        Some(entry)
+    }
+
+    /// Cache the front matter and contents `source` produced.
+    pub(crate) fn put(&self, source: &Source, page: &Page) {
+        let key = key(source);
+        let entry = EntryRef {
+            front_matter: &page.metadata.front_matter,
+            contents: &page.contents,
+            warnings: &page.warnings,
+        };
+
+        // Front matter which JSON cannot represent (e.g. a mapping with
+        // non-string keys) just does not get cached.
+        if let Ok(contents) = serde_json::to_string(&entry) {
+            if std::fs::write(self.entry_path(&key), contents).is_ok() {
+                self.use_key(&source.path, key);
+            }
+        }
+    }
+
+    /// The syntax definitions the cache was built with, if they were stored.
+    /// Since the site's syntax definitions are part of the cache's
+    /// fingerprint, these are always the current ones.
+    pub(crate) fn get_syntaxes(&self) -> Option<SyntaxSet> {
+        syntect::dumps::from_dump_file(self.dir.join(SYNTAXES)).ok()
+    }
+
+    /// Store the syntax definitions, to skip loading them on the next build.
+    pub(crate) fn put_syntaxes(&self, syntax_set: &SyntaxSet) {
+        let path = self.dir.join(SYNTAXES);
+        if syntect::dumps::dump_to_file(syntax_set, &path).is_err() {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+
+    /// Stop keeping the entry for the source at `path`, which no longer exists.
+    pub(crate) fn forget(&self, path: &Path) {
+        self.used
+            .lock()
+            .expect("cache lock is never poisoned")
+            .remove(path);
+    }
+
+    /// Remove every entry other than the latest one used for each source since
+    /// the cache was opened. Only call this after loading *all* of a site's
+    /// content!
+    pub(crate) fn prune(&self) {
+        let used = self.used.lock().expect("cache lock is never poisoned");
+        let used: HashSet<&str> = used.values().map(String::as_str).collect();
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+
+        for path in entries.filter_map(Result::ok).map(|entry| entry.path()) {
+            let stem = path.file_stem().and_then(|stem| stem.to_str());
+            let is_unused = path.extension() == Some("entry".as_ref())
+                && !matches!(stem, Some(stem) if used.contains(stem));
+            if is_unused {
+                let _ = std::fs::remove_file(path);
+            }
+        }
+    }
+
+    fn entry_path(&self, key: &str) -> PathBuf {
+        self.dir.join(key).with_extension("entry")
+    }
+
+    fn use_key(&self, path: &Path, key: String) {
+        self.used
+            .lock()
+            .expect("cache lock is never poisoned")
+            .insert(path.to_owned(), key);
+    }
+}
+
+/// The same contents at a different path produce a different page (at the
+/// very least, a different slug), so the key includes both.
+fn key(source: &Source) -> String {
+    let mut bytes = source.path.to_string_lossy().into_owned().into_bytes();
+    bytes.push(0);
+    bytes.extend_from_slice(source.contents.as_bytes());
+    Uuid::new_v5(&Uuid::NAMESPACE_OID, &bytes).to_string()
+}
+
+/// A hash of everything besides a source file itself which goes into building
+/// a page from it: the site config, every data cascade file, and the site's own
+/// syntax definitions used for highlighting. (The default syntax definitions
+/// only change along with lx itself.)
+pub(crate) fn fingerprint(
+    config_path: &Path,
+    cascade_paths: &[PathBuf],
+    syntax_paths: &[PathBuf],
+) -> Result<String, Error> {
+    let mut bytes = Vec::new();
+    let paths = std::iter::once(config_path)
+        .chain(cascade_paths.iter().map(PathBuf::as_path))
+        .chain(syntax_paths.iter().map(PathBuf::as_path));
+    for path in paths {
+        let contents = std::fs::read(path).map_err(|e| Error::io(path, e))?;
+        bytes.extend_from_slice(path.to_string_lossy().as_bytes());
+        bytes.push(0);
+        bytes.extend(contents);
+        bytes.push(0);
+    }
+
+    Ok(Uuid::new_v5(&Uuid::NAMESPACE_OID, &bytes).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::build::{Mode, Site};
+    use crate::test_site::TestSite;
+    use crate::watch::{rebuild, Changes};
+
+    fn entries(root: &Path) -> Vec<PathBuf> {
+        std::fs::read_dir(root.join(DIR))
+            .expect("has a cache")
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|path| path.extension() == Some("entry".as_ref()))
+            .collect()
+    }
+
+    fn rendered(root: &Path) -> String {
+        std::fs::read_to_string(root.join("public/hello-world.html")).expect("has output")
+    }
+
+    #[test]
+    fn reuses_cached_content_unless_told_not_to() {
+        let site = TestSite::new("cache-reuse", "");
+        let root = site.root.clone();
+        crate::build(root.clone(), true).expect("builds");
+        let cached = entries(&root);
+        assert_eq!(cached.len(), 1);
+
+        // Tamper with the entry, so it is obvious when the build used it.
+        let entry = std::fs::read_to_string(&cached[0]).unwrap();
+        std::fs::write(&cached[0], entry.replace("Welcome", "Cached")).unwrap();
+        crate::build(root.clone(), true).expect("builds");
+        assert!(rendered(&root).contains("Cached to your new site!"));
+
+        crate::build(root.clone(), false).expect("builds");
+        assert!(rendered(&root).contains("Welcome to your new site!"));
+    }
+
+    #[test]
+    fn invalidates_when_the_config_changes() {
+        let site = TestSite::new("cache-invalidate", "");
+        let root = site.root.clone();
+        crate::build(root.clone(), true).expect("builds");
+        let cached = entries(&root);
+        let entry = std::fs::read_to_string(&cached[0]).unwrap();
+        std::fs::write(&cached[0], entry.replace("Welcome", "Cached")).unwrap();
+
+        let config_path = root.join("_data/config.json5");
+        let config = std::fs::read_to_string(&config_path).unwrap();
+        std::fs::write(&config_path, format!("// changed\n{}", config)).unwrap();
+        crate::build(root.clone(), true).expect("builds");
+        assert!(rendered(&root).contains("Welcome to your new site!"));
+    }
+
+    #[test]
+    fn invalidates_when_the_format_changes() {
+        let site = TestSite::new("cache-format", "");
+        let root = site.root.clone();
+        crate::build(root.clone(), true).expect("builds");
+        let manifest_path = root.join(DIR).join(MANIFEST);
+        let manifest = std::fs::read_to_string(&manifest_path).unwrap();
+        std::fs::write(
+            &manifest_path,
+            manifest.replace(&format!("\"format\":{}", FORMAT), "\"format\":0"),
+        )
+        .unwrap();
+
+        let fingerprint = serde_json::from_str::<Manifest>(&manifest)
+            .unwrap()
+            .fingerprint;
+        Cache::open(&root, fingerprint).expect("opens the cache");
+        assert!(entries(&root).is_empty());
+    }
+
+    #[test]
+    fn keeps_warnings_from_rendering() {
+        let site = TestSite::new("cache-warnings", "");
+        site.write(&[(
+            "content/code.md",
+            "---\ntitle: Code\n---\n```rust {colour=red}\nfn main() {}\n```\n",
+        )]);
+        let root = site.root.clone();
+        let code_warnings = |site: &Site| {
+            site.pages
+                .iter()
+                .find(|page| page.metadata.slug == "code")
+                .expect("has the page")
+                .warnings
+                .clone()
+        };
+
+        let (fresh, _) = Site::load(root.clone(), true, Mode::Development).expect("loads");
+        let warnings = code_warnings(&fresh);
+        assert!(warnings[0].contains("'colour'"), "{:?}", warnings);
+        drop(fresh);
+
+        // Tamper with the entry, so it is obvious when the build used it.
+        for entry in entries(&root) {
+            let contents = std::fs::read_to_string(&entry).unwrap();
+            std::fs::write(&entry, contents.replace("'colour'", "'tint'")).unwrap();
+        }
+        let (cached, _) = Site::load(root.clone(), true, Mode::Development).expect("loads");
+        let cached_warnings = code_warnings(&cached);
+        assert_eq!(cached_warnings.len(), warnings.len());
+        assert!(
+            cached_warnings[0].contains("'tint'"),
+            "{:?}",
+            cached_warnings
+        );
+    }
+
+    #[test]
+    fn watching_prunes_entries_for_changed_content() {
+        let site = TestSite::new("cache-watch-prune", "");
+        let root = site.root.clone();
+        let (mut site, errors) = Site::load(root.clone(), true, Mode::Development).expect("loads");
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert!(site.write_all().is_empty());
+        assert_eq!(entries(&root).len(), 1);
+
+        let post = root.join("content/hello-world.md");
+        let contents = std::fs::read_to_string(&post).unwrap();
+        std::fs::write(&post, contents.replace("Welcome", "Hello")).unwrap();
+        let changes = Changes::classify(&site, std::iter::once(post.clone()).collect());
+        assert!(rebuild(&mut site, changes).is_empty());
+        assert_eq!(entries(&root).len(), 1);
+
+        std::fs::remove_file(&post).unwrap();
+        let changes = Changes::classify(&site, std::iter::once(post).collect());
+        assert!(rebuild(&mut site, changes).is_empty());
+        assert!(entries(&root).is_empty());
+    }
+
+    #[test]
+    fn prunes_entries_for_changed_content() {
+        let site = TestSite::new("cache-prune", "");
+        let root = site.root.clone();
+        crate::build(root.clone(), true).expect("builds");
+
+        let post = root.join("content/hello-world.md");
+        let contents = std::fs::read_to_string(&post).unwrap();
+        std::fs::write(&post, contents.replace("Welcome", "Hello")).unwrap();
+        crate::build(root.clone(), true).expect("builds");
+        assert_eq!(entries(&root).len(), 1);
+
+        std::fs::remove_file(&post).unwrap();
+        crate::build(root.clone(), true).expect("builds");
+        assert!(entries(&root).is_empty());
+    }
+}
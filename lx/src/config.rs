@@ -1,12 +1,19 @@
 mod email;
+pub(crate) mod permalink;
 
-use std::path::{Path, PathBuf};
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
 
-use serde_derive::Deserialize;
+use chrono_tz::Tz;
+use serde_derive::{Deserialize, Serialize};
+use serde_yaml::Value;
 
 use email::Email;
+use permalink::Pattern;
 
-#[derive(Deserialize, Debug)]
+use crate::error::Error;
+
+#[derive(Deserialize, Serialize, Debug)]
 pub struct Config {
     pub(crate) url: String,
     pub(crate) repo: String,
@@ -15,36 +22,457 @@ pub struct Config {
     pub(crate) description: String,
     pub(crate) author: Author,
     pub(crate) output: PathBuf,
+    /// The time zone to use when generating dates, e.g. `America/Denver`. Uses
+    /// the system's local time zone if not set.
+    pub(crate) timezone: Option<Tz>,
+    #[serde(default)]
+    pub(crate) layouts: Layouts,
+    #[serde(default)]
+    pub(crate) cascade: CascadeRules,
+    #[serde(default)]
+    pub(crate) feeds: Feeds,
+    #[serde(default)]
+    pub(crate) taxonomies: Vec<Taxonomy>,
+    #[serde(default)]
+    pub(crate) permalinks: Permalinks,
+    #[serde(default)]
+    pub(crate) redirects: Redirects,
+    #[serde(default)]
+    pub(crate) other_content: OtherContent,
+    #[serde(default)]
+    pub(crate) styles: Styles,
+    #[serde(default)]
+    pub(crate) themes: Themes,
+    #[serde(default)]
+    pub(crate) syntaxes: Syntaxes,
+    #[serde(default)]
+    pub(crate) code_blocks: CodeBlocks,
 }
 
 impl Config {
-    pub fn from_file(path: &Path) -> Result<Config, String> {
-        let data = std::fs::read_to_string(path)
-            .map_err(|e| format!("could not read '{}'\n{}", &path.to_string_lossy(), e))?;
-        let mut config: Config = json5::from_str(&data)
-            .map_err(|e| format!("could not parse '{}':\n{}", &path.display(), e))?;
-
-        config.output = std::fs::canonicalize(
-            path.parent()
-                .ok_or_else(|| String::from("config file will have a parent dir"))?
-                .join(config.output),
-        )
-        .map_err(|e| e.to_string())?;
+    pub fn from_file(path: &Path) -> Result<Config, Error> {
+        let data = std::fs::read_to_string(path).map_err(|e| Error::io(path, e))?;
+        let mut config: Config = json5::from_str(&data).map_err(|e| Error::config(path, e))?;
+
+        let data_dir = path.parent().ok_or_else(|| Error::Config {
+            path: path.to_owned(),
+            location: None,
+            message: String::from("config file will have a parent dir"),
+        })?;
+
+        // The output directory is not usually checked in, so it may not exist
+        // yet (e.g. on a fresh clone or a just-initialized site). Writing the
+        // site creates it; reading the config should not.
+        let output = data_dir.join(config.output);
+        config.output = match std::fs::canonicalize(&output) {
+            Ok(output) => output,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => normalize(&output),
+            Err(e) => return Err(Error::io(&output, e)),
+        };
+
+        // The layouts directory is relative to the site root, i.e. the parent of
+        // the `_data` directory containing the config file.
+        let site_root = data_dir.parent().ok_or_else(|| Error::Config {
+            path: path.to_owned(),
+            location: None,
+            message: String::from("data dir will have a parent dir"),
+        })?;
+        config.layouts.directory = site_root.join(config.layouts.directory);
+        // So are the styles, themes, and syntaxes directories.
+        config.styles.directory = config
+            .styles
+            .directory
+            .map(|directory| site_root.join(directory));
+        config.themes.directory = site_root.join(config.themes.directory);
+        config.themes.stylesheets = site_root.join(config.themes.stylesheets);
+        config.syntaxes.directory = site_root.join(config.syntaxes.directory);
 
         Ok(config)
     }
+
+    /// Where the page for `slug` goes in the output directory.
+    pub(crate) fn output_path(&self, slug: &str) -> PathBuf {
+        if self.permalinks.pretty {
+            self.output.join(slug).join("index.html")
+        } else {
+            self.output.join(slug).with_extension("html")
+        }
+    }
+
+    /// The full URL for the page for `slug`.
+    pub(crate) fn url_for(&self, slug: &str) -> String {
+        format!(
+            "{}{}",
+            self.url.trim_end_matches('/'),
+            self.url_path_for(slug)
+        )
+    }
+
+    /// The URL for the page for `slug`, relative to the root of the site.
+    pub(crate) fn url_path_for(&self, slug: &str) -> String {
+        if self.permalinks.pretty {
+            format!("/{}/", slug)
+        } else {
+            format!("/{}", slug)
+        }
+    }
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug)]
 pub struct Title {
-    normal: String,
-    stylized: String,
+    pub(crate) normal: String,
+    pub(crate) stylized: String,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug)]
 pub struct Author {
     pub(crate) name: String,
     #[serde(deserialize_with = "Email::de_from_str")]
     pub(crate) email: Email,
     pub(crate) links: Vec<String>,
 }
+
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(default)]
+pub struct Layouts {
+    /// The directory containing the Tera templates, relative to the site root.
+    pub(crate) directory: PathBuf,
+    /// The layout to use for any page which does not specify one.
+    pub(crate) default: String,
+}
+
+impl Default for Layouts {
+    fn default() -> Self {
+        Layouts {
+            directory: PathBuf::from("_layouts"),
+            default: String::from("base.html"),
+        }
+    }
+}
+
+/// How to combine data from `config.lx.yaml` files with the data further down
+/// the content tree.
+#[derive(Deserialize, Serialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct CascadeRules {
+    /// How to merge each list, keyed by its dotted path in the metadata, e.g.
+    /// `tags` or `book.editors`. Lists not named here are replaced.
+    pub(crate) lists: HashMap<String, ListMerge>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ListMerge {
+    /// Keep the items from further up the tree, and add any new items from
+    /// further down.
+    Append,
+    /// Use only the items from furthest down the tree.
+    #[default]
+    Replace,
+}
+
+/// How to construct the URLs for items, and where to write them.
+#[derive(Deserialize, Serialize, Debug, Default)]
+#[serde(default)]
+pub struct Permalinks {
+    /// Write each page to `<slug>/index.html` and link to it as `<slug>/`,
+    /// rather than writing it to `<slug>.html` and linking to it as `<slug>`.
+    pub(crate) pretty: bool,
+    /// The permalink patterns (see [`permalink`]) for items which do not set a
+    /// `permalink` of their own, keyed by the directory under `content` they
+    /// apply to, e.g. `journal`. The nearest directory's pattern wins.
+    pub(crate) sections: HashMap<String, Pattern>,
+}
+
+impl Permalinks {
+    /// The pattern for items in `dir`, relative to `content`, if any.
+    pub(crate) fn pattern_for(&self, dir: &str) -> Option<&Pattern> {
+        let mut dir = dir.trim_matches('/');
+        loop {
+            if let Some(pattern) = self.sections.get(dir) {
+                return Some(pattern);
+            }
+            if dir.is_empty() {
+                return None;
+            }
+            dir = dir
+                .rfind('/')
+                .map(|index| &dir[..index])
+                .unwrap_or_default();
+        }
+    }
+}
+
+/// What to do with the site's `_redirects` file (see [`crate::redirects`]),
+/// beyond copying it into the output.
+#[derive(Deserialize, Serialize, Debug, Default)]
+#[serde(default)]
+pub struct Redirects {
+    /// Also write an HTML page for each redirect from a single path, which
+    /// redirects with `<meta http-equiv="refresh">`, for hosts which do not
+    /// support `_redirects`.
+    pub(crate) html_stubs: bool,
+}
+
+/// Files to copy into the output as-is (see [`crate::assets`]).
+#[derive(Deserialize, Serialize, Debug, Default)]
+#[serde(default)]
+pub struct OtherContent {
+    /// Directories, relative to the site root, whose contents go at the root of
+    /// the output, e.g. `_static`.
+    pub(crate) copy: Vec<PathBuf>,
+    /// Globs, relative to the site root, for files never to copy, e.g.
+    /// `**/*.njk` or `_static/drafts/**`.
+    #[serde(with = "globs")]
+    pub(crate) exclude: Vec<glob::Pattern>,
+    /// Put a hash of their contents in the names of the stylesheets, scripts,
+    /// fonts, and images in the `copy` directories, so they can be cached
+    /// indefinitely.
+    pub(crate) fingerprint: bool,
+}
+
+/// The site's Sass stylesheets (see [`crate::styles`]).
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(default)]
+pub struct Styles {
+    /// The directory containing the `.scss` and `.sass` files, relative to the
+    /// site root. Every file in it whose name does not start with `_` (i.e.
+    /// which is not a partial) is compiled. If not set, nothing is.
+    pub(crate) directory: Option<PathBuf>,
+    /// Where the compiled CSS goes, relative to the output directory.
+    pub(crate) output: PathBuf,
+}
+
+impl Default for Styles {
+    fn default() -> Self {
+        Styles {
+            directory: None,
+            output: PathBuf::from("styles"),
+        }
+    }
+}
+
+/// The syntax highlighting themes (see [`crate::themes`]).
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(default)]
+pub struct Themes {
+    /// The theme for light mode, e.g. `InspiredGitHub`.
+    pub(crate) light: String,
+    /// The theme for dark mode, e.g. `base16-ocean.dark`.
+    pub(crate) dark: String,
+    /// A directory of `.tmTheme` files to use alongside the default themes,
+    /// relative to the site root.
+    pub(crate) directory: PathBuf,
+    /// Where `lx themes` writes the stylesheets, relative to the site root.
+    pub(crate) stylesheets: PathBuf,
+}
+
+impl Default for Themes {
+    fn default() -> Self {
+        Themes {
+            light: String::from("InspiredGitHub"),
+            dark: String::from("base16-ocean.dark"),
+            directory: PathBuf::from("_themes"),
+            stylesheets: PathBuf::from("_themes"),
+        }
+    }
+}
+
+/// Syntax definitions for highlighting code (see [`crate::syntaxes`]).
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(default)]
+pub struct Syntaxes {
+    /// A directory of `.sublime-syntax` files to use alongside the default
+    /// syntax definitions, relative to the site root.
+    pub(crate) directory: PathBuf,
+    /// Store the combined syntax definitions in the build cache, so builds can
+    /// start without loading them all again.
+    pub(crate) dump: bool,
+}
+
+impl Default for Syntaxes {
+    fn default() -> Self {
+        Syntaxes {
+            directory: PathBuf::from("_syntaxes"),
+            dump: false,
+        }
+    }
+}
+
+/// The markup for code blocks in Markdown content.
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(default)]
+pub struct CodeBlocks {
+    /// Prepended to the language's name for the class on `<code>`, e.g.
+    /// `language-` for `language-rust`.
+    pub(crate) class_prefix: String,
+    /// Put the language's full name on `<pre>` as `data-lang`, e.g. for styles
+    /// to show it with `content: attr(data-lang)`.
+    pub(crate) data_lang: bool,
+}
+
+impl Default for CodeBlocks {
+    fn default() -> Self {
+        CodeBlocks {
+            class_prefix: String::from("language-"),
+            data_lang: true,
+        }
+    }
+}
+
+/// `path` with its `.` and `..` components resolved without looking at the
+/// file system, for a path which may not exist yet.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+mod globs {
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub(super) fn deserialize<'de, D>(deserializer: D) -> Result<Vec<glob::Pattern>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Vec::<String>::deserialize(deserializer)?
+            .iter()
+            .map(|source| {
+                glob::Pattern::new(source)
+                    .map_err(|e| de::Error::custom(format!("invalid glob '{}': {}", source, e)))
+            })
+            .collect()
+    }
+
+    pub(super) fn serialize<S: Serializer>(
+        patterns: &[glob::Pattern],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(patterns.iter().map(glob::Pattern::as_str))
+    }
+}
+
+/// Which feeds to generate. There is always a site-wide feed; each of the
+/// `sections` gets its own feed as well.
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(default)]
+pub struct Feeds {
+    /// The title of the site-wide feed. Defaults to the site title.
+    pub(crate) title: Option<String>,
+    /// The formats to emit every feed in, unless a section says otherwise.
+    pub(crate) engines: Vec<Engine>,
+    pub(crate) sections: Vec<SectionFeed>,
+}
+
+impl Default for Feeds {
+    fn default() -> Self {
+        Feeds {
+            title: None,
+            engines: vec![Engine::Json],
+            sections: Vec::new(),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct SectionFeed {
+    /// The top-level directory of the section, e.g. `journal`.
+    pub(crate) section: String,
+    pub(crate) title: String,
+    /// The formats to emit this section's feed in, if different from the
+    /// site-wide `engines`.
+    pub(crate) engines: Option<Vec<Engine>>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Engine {
+    #[serde(rename = "RSS")]
+    Rss,
+    Atom,
+    #[serde(rename = "JSON")]
+    Json,
+}
+
+/// A way of grouping content, e.g. by tag or by category.
+#[derive(Deserialize, Serialize, Debug)]
+pub struct Taxonomy {
+    /// The name of the taxonomy, which is also the root of its URLs.
+    pub(crate) name: String,
+    /// The front matter key to find each item's terms under, if different from
+    /// the `name`: e.g. a taxonomy named `tag` with items' `tags`.
+    pub(crate) key: Option<String>,
+    #[serde(rename = "type")]
+    pub(crate) kind: TaxonomyKind,
+    /// Whether every item must have at least one term.
+    #[serde(default)]
+    pub(crate) required: bool,
+    /// Whether terms can be nested.
+    #[serde(default)]
+    pub(crate) hierarchical: bool,
+    /// The term(s) for items which do not specify any.
+    pub(crate) default: Option<Value>,
+    /// The maximum number of terms any one item may have.
+    pub(crate) limit: Option<usize>,
+    pub(crate) templates: TaxonomyTemplates,
+    /// Whether to generate a feed for each term.
+    #[serde(default)]
+    pub(crate) generate_feeds: bool,
+    /// For `temporal` taxonomies: whether items with a title but no date are
+    /// archived by their `updated` date. They are left out by default.
+    #[serde(default)]
+    pub(crate) include_title_only: bool,
+}
+
+impl Taxonomy {
+    pub(crate) fn key(&self) -> &str {
+        self.key.as_deref().unwrap_or(&self.name)
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TaxonomyKind {
+    /// Items may have any number of terms, e.g. tags.
+    Multiple,
+    /// Items are grouped by date.
+    Temporal,
+    /// Items either belong or do not, e.g. standalone pages.
+    Binary,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct TaxonomyTemplates {
+    /// The layout for the page listing every term, if there should be one.
+    pub(crate) list: Option<String>,
+    /// The layout for the page listing every item with a given term.
+    pub(crate) item: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_site::TestSite;
+
+    #[test]
+    fn reading_the_config_does_not_create_the_output() {
+        let site = TestSite::new("config-output", "");
+        let config = std::fs::read_to_string(site.config_path()).unwrap();
+        let config = config.replace("\"../public\"", "\"../dist/./site\"");
+        std::fs::write(site.config_path(), config).unwrap();
+
+        let config = site.config();
+        assert_eq!(config.output, site.root.join("dist/site"));
+        assert!(!config.output.exists());
+
+        crate::build(site.root.clone(), false).expect("builds");
+        assert!(config.output.join("hello-world.html").is_file());
+        assert_eq!(site.config().output, config.output);
+    }
+}
@@ -2,7 +2,7 @@ use std::str::FromStr;
 
 use lazy_static::lazy_static;
 use regex::Regex;
-use serde::{de, Deserialize, Deserializer};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 
 lazy_static! {
     /// An incredibly stupid email-"parsing" regex.
@@ -33,7 +33,7 @@ impl std::str::FromStr for Email {
         EMAIL_RE
             .captures(s)
             .ok_or(format!("could not parse {}", s))
-            .and_then(|captures| match (captures.get(0), captures.get(1)) {
+            .and_then(|captures| match (captures.get(1), captures.get(2)) {
                 (Some(local), Some(host)) => Ok(Email {
                     local: local.as_str().to_owned(),
                     host: host.as_str().to_owned(),
@@ -45,3 +45,15 @@ impl std::str::FromStr for Email {
             .map_err(|e| format!("email validation error: {}", e))
     }
 }
+
+impl std::fmt::Display for Email {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}@{}", self.local, self.host)
+    }
+}
+
+impl Serialize for Email {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
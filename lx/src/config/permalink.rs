@@ -0,0 +1,236 @@
+//! Permalink patterns, e.g. `/journal/{year}/{month}/{slug}/`, which build an
+//! item's URL out of its metadata.
+//!
+//! The placeholders are:
+//!
+//! - `{slug}`: the item's file name, slugified
+//! - `{path}`: the directory containing the item, relative to `content`
+//! - `{collection}`: the top-level directory containing the item
+//! - `{title}`: the item's title, slugified
+//! - `{series}`: the name of the item's series, slugified
+//! - `{year}`, `{month}`, `{day}`: the item's date, zero-padded
+//! - `{date:<format>}`: the item's date in any [`chrono` format][format], e.g.
+//!   `{date:%Y-%m-%d-%H%M}`
+//!
+//! [format]: https://docs.rs/chrono/latest/chrono/format/strftime/index.html
+
+use std::fmt::Write;
+
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, FixedOffset};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Pattern {
+    source: String,
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Literal(String),
+    Slug,
+    Path,
+    Collection,
+    Title,
+    Series,
+    Year,
+    Month,
+    Day,
+    Date(String),
+}
+
+/// Everything about an item a pattern can use.
+pub(crate) struct Fields<'a> {
+    /// `None` if the file name has nothing to make a slug from, e.g. `!!!.md`.
+    pub(crate) slug: Option<&'a str>,
+    pub(crate) path: &'a str,
+    pub(crate) title: Option<&'a str>,
+    pub(crate) series: Option<&'a str>,
+    pub(crate) date: Option<&'a DateTime<FixedOffset>>,
+}
+
+impl Pattern {
+    pub(crate) fn parse(source: &str) -> Result<Pattern, String> {
+        let mut parts = Vec::new();
+        let mut rest = source;
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                parts.push(Part::Literal(rest[..start].to_string()));
+            }
+            let end = rest[start..]
+                .find('}')
+                .map(|end| start + end)
+                .ok_or_else(|| format!("unclosed '{{' in permalink pattern '{}'", source))?;
+            parts.push(Part::parse(&rest[start + 1..end], source)?);
+            rest = &rest[end + 1..];
+        }
+        if rest.contains('}') {
+            return Err(format!("unopened '}}' in permalink pattern '{}'", source));
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Literal(rest.to_string()));
+        }
+
+        Ok(Pattern {
+            source: source.to_string(),
+            parts,
+        })
+    }
+
+    /// The slug for an item, i.e. its URL relative to the site root, without
+    /// any leading or trailing `/`.
+    pub(crate) fn expand(&self, fields: &Fields) -> Result<String, String> {
+        let missing = |field: &str| {
+            format!(
+                "permalink pattern '{}' needs a {}, but the item has none",
+                self.source, field
+            )
+        };
+        let date = || fields.date.ok_or_else(|| missing("date"));
+
+        let mut expanded = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Literal(literal) => expanded.push_str(literal),
+                Part::Slug => expanded.push_str(fields.slug.ok_or_else(|| {
+                    format!(
+                        "permalink pattern '{}' needs a slug, but the item's file name has nothing to make one from",
+                        self.source
+                    )
+                })?),
+                Part::Path => expanded.push_str(fields.path),
+                Part::Collection => {
+                    expanded.push_str(fields.path.split('/').next().unwrap_or_default())
+                }
+                Part::Title => {
+                    let title = fields.title.ok_or_else(|| missing("title"))?;
+                    expanded.push_str(&slug::slugify(title));
+                }
+                Part::Series => {
+                    let series = fields.series.ok_or_else(|| missing("series name"))?;
+                    expanded.push_str(&slug::slugify(series));
+                }
+                Part::Year => expanded.push_str(&date()?.format("%Y").to_string()),
+                Part::Month => expanded.push_str(&date()?.format("%m").to_string()),
+                Part::Day => expanded.push_str(&date()?.format("%d").to_string()),
+                Part::Date(format) => {
+                    write!(expanded, "{}", date()?.format(format)).map_err(|e| e.to_string())?
+                }
+            }
+        }
+
+        // e.g. `{path}/{slug}` for an item at the root of `content`.
+        let segments: Vec<&str> = expanded
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .collect();
+        if segments.is_empty() {
+            return Err(format!(
+                "permalink pattern '{}' produced an empty slug",
+                self.source
+            ));
+        }
+        Ok(segments.join("/"))
+    }
+}
+
+impl Part {
+    fn parse(placeholder: &str, source: &str) -> Result<Part, String> {
+        match placeholder {
+            "slug" => Ok(Part::Slug),
+            "path" => Ok(Part::Path),
+            "collection" => Ok(Part::Collection),
+            "title" => Ok(Part::Title),
+            "series" => Ok(Part::Series),
+            "year" => Ok(Part::Year),
+            "month" => Ok(Part::Month),
+            "day" => Ok(Part::Day),
+            _ => match placeholder.strip_prefix("date:") {
+                Some(format) => {
+                    if StrftimeItems::new(format).any(|item| item == Item::Error) {
+                        Err(format!(
+                            "invalid date format '{}' in permalink pattern '{}'",
+                            format, source
+                        ))
+                    } else {
+                        Ok(Part::Date(format.to_string()))
+                    }
+                }
+                None => Err(format!(
+                    "unknown placeholder '{{{}}}' in permalink pattern '{}'",
+                    placeholder, source
+                )),
+            },
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Pattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let source = String::deserialize(deserializer)?;
+        Pattern::parse(&source).map_err(de::Error::custom)
+    }
+}
+
+impl Serialize for Pattern {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields<'a>(date: Option<&'a DateTime<FixedOffset>>) -> Fields<'a> {
+        Fields {
+            slug: Some("hello-world"),
+            path: "journal/2020",
+            title: Some("Hello, World!"),
+            series: None,
+            date,
+        }
+    }
+
+    #[test]
+    fn expands_placeholders() {
+        let date = DateTime::parse_from_rfc3339("2020-03-04T06:34:00-07:00").unwrap();
+        let expand = |pattern: &str| {
+            Pattern::parse(pattern)
+                .unwrap()
+                .expand(&fields(Some(&date)))
+        };
+
+        assert_eq!(
+            expand("/journal/{year}/{month}/{slug}/").unwrap(),
+            "journal/2020/03/hello-world"
+        );
+        assert_eq!(
+            expand("/notes/{date:%Y-%m-%d-%H%M}/").unwrap(),
+            "notes/2020-03-04-0634"
+        );
+        assert_eq!(
+            expand("{collection}/{day}-{title}").unwrap(),
+            "journal/04-hello-world"
+        );
+        assert_eq!(expand("{path}/{slug}").unwrap(), "journal/2020/hello-world");
+    }
+
+    #[test]
+    fn reports_missing_fields() {
+        let pattern = Pattern::parse("/{year}/{slug}").unwrap();
+        assert!(pattern.expand(&fields(None)).is_err());
+
+        let pattern = Pattern::parse("/{series}/{slug}").unwrap();
+        assert!(pattern.expand(&fields(None)).is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_patterns() {
+        assert!(Pattern::parse("/{slug").is_err());
+        assert!(Pattern::parse("/slug}").is_err());
+        assert!(Pattern::parse("/{nope}/").is_err());
+        assert!(Pattern::parse("/{date:%Q}/").is_err());
+    }
+}
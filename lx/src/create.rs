@@ -0,0 +1,213 @@
+//! Create new content from the site's archetypes: Markdown files in
+//! `_data/archetypes/` whose front matter and body are the starting point for
+//! each new item of that kind, e.g. `_data/archetypes/notes.md` for `lx create
+//! notes`.
+
+use std::convert::TryFrom;
+use std::path::{Component, Path, PathBuf};
+
+use chrono::{DateTime, FixedOffset, Local, Offset, Utc};
+use serde_yaml::{Mapping, Value};
+
+use crate::config::Config;
+use crate::error::Error;
+use crate::page::components::Components;
+use crate::page::metadata::Metadata;
+use crate::page::Source;
+
+/// Create a new item of `kind` in the `content/<kind>` section of the site,
+/// returning the path to the new file.
+///
+/// The file is named for the `title` if there is one, or else for the current
+/// time, e.g. `content/notes/2020-04-29-0634.md`. The `kind` must be a single
+/// directory name, and the `title` must have something in it to name the file.
+/// An existing file is never overwritten.
+pub fn create(
+    site_directory: PathBuf,
+    kind: &str,
+    title: Option<String>,
+) -> Result<PathBuf, Error> {
+    let site_directory =
+        std::fs::canonicalize(&site_directory).map_err(|e| Error::io(&site_directory, e))?;
+    let config = Config::from_file(&site_directory.join("_data/config.json5"))?;
+
+    let section_dir = site_directory.join("content").join(kind);
+    let mut components = Path::new(kind).components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(_)), None) => {}
+        _ => {
+            return Err(Error::Output {
+                path: section_dir,
+                message: format!("'{}' is not the name of a section", kind),
+            })
+        }
+    }
+
+    // Not `with_extension`, which would replace anything after a `.` in a kind
+    // like `notes.v2`.
+    let archetype_path = site_directory
+        .join("_data/archetypes")
+        .join(format!("{}.md", kind));
+    let archetype = Source {
+        contents: std::fs::read_to_string(&archetype_path)
+            .map_err(|e| Error::io(&archetype_path, e))?,
+        path: archetype_path,
+    };
+    let Components { header, body } = Components::try_from(&archetype)?;
+
+    let now = now(&config);
+    let header = front_matter(&archetype.path, header, title.as_deref(), &now)?;
+    Metadata::validate(&archetype.path, &header)?;
+
+    let file_name = match &title {
+        Some(title) => slug::slugify(title),
+        None => now.format("%Y-%m-%d-%H%M").to_string(),
+    };
+    if file_name.is_empty() {
+        return Err(Error::Slug {
+            path: section_dir,
+            message: format!(
+                "the title '{}' has no letters or numbers to use in a file name",
+                title.unwrap_or_default()
+            ),
+        });
+    }
+    let path = section_dir.join(format!("{}.md", file_name));
+    if path.exists() {
+        // Untitled items are named to the minute, so may collide.
+        let message = match title {
+            Some(_) => String::from("choose another title"),
+            None => String::from("wait a minute, or give it a title"),
+        };
+        return Err(Error::Exists { path, message });
+    }
+
+    if let Some(containing_dir) = path.parent() {
+        std::fs::create_dir_all(containing_dir).map_err(|e| Error::io(&path, e))?;
+    }
+    std::fs::write(&path, format!("---\n{}---\n{}", header, body))
+        .map_err(|e| Error::io(&path, e))?;
+
+    Ok(path)
+}
+
+/// The current time in the site's configured time zone.
+fn now(config: &Config) -> DateTime<FixedOffset> {
+    match config.timezone {
+        Some(tz) => {
+            let now = Utc::now().with_timezone(&tz);
+            now.with_timezone(&now.offset().fix())
+        }
+        None => Local::now().into(),
+    }
+}
+
+/// The front matter of the archetype at `path` with the `title` (if any) and
+/// `date` filled in, ahead of everything else the archetype supplies.
+fn front_matter(
+    path: &Path,
+    header: &str,
+    title: Option<&str>,
+    date: &DateTime<FixedOffset>,
+) -> Result<String, Error> {
+    let archetype = match serde_yaml::from_str(header).map_err(|e| Error::front_matter(path, e))? {
+        Value::Null => Mapping::new(),
+        Value::Mapping(mapping) => mapping,
+        _ => return Err(Error::invalid_front_matter(path, "must be a mapping")),
+    };
+
+    let mut front_matter = Mapping::new();
+    if let Some(title) = title {
+        front_matter.insert("title".into(), title.into());
+    }
+    front_matter.insert(
+        "date".into(),
+        date.format("%Y-%m-%dT%H:%M:%S%:z").to_string().into(),
+    );
+    for (key, value) in archetype {
+        if !front_matter.contains_key(&key) {
+            front_matter.insert(key, value);
+        }
+    }
+
+    serde_yaml::to_string(&front_matter)
+        .map_err(|e| Error::invalid_front_matter(path, e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_site::TestSite;
+
+    #[test]
+    fn creates_items_from_archetypes() {
+        let test_site = TestSite::new("create", "");
+        test_site.write(&[(
+            "_data/archetypes/notes.md",
+            "---\ndate: ~\ntags: [note]\n---\nWrite something!\n",
+        )]);
+        let site = test_site.root.clone();
+
+        let path = create(site.clone(), "notes", Some(String::from("Hello, Notes!")))
+            .expect("creates a titled note");
+        assert!(path.ends_with("content/notes/hello-notes.md"));
+        let contents = std::fs::read_to_string(&path).expect("reads the note");
+        assert!(contents.starts_with("---\ntitle: Hello, Notes!\ndate: "));
+        assert!(contents.contains("tags:\n- note\n"));
+        assert!(contents.ends_with("---\nWrite something!\n"));
+
+        let path = create(site.clone(), "notes", None).expect("creates an untitled note");
+        let file_name = path.file_stem().and_then(|stem| stem.to_str()).unwrap();
+        assert_eq!(file_name.len(), "2020-04-29-0634".len());
+
+        let result = create(site.clone(), "notes", Some(String::from("Hello, Notes!")));
+        assert!(matches!(result, Err(Error::Exists { .. })));
+        match create(site.clone(), "notes", None) {
+            Err(Error::Exists { path: existing, .. }) => assert_eq!(existing, path),
+            // Unless the minute turned over in between.
+            Ok(other) => assert_ne!(other, path),
+            Err(e) => panic!("expected an existing file error, got {:?}", e),
+        }
+        assert!(create(site.clone(), "journal", None).is_err());
+
+        crate::build(site.clone(), false).expect("the new notes build");
+    }
+
+    #[test]
+    fn only_creates_files_in_a_section() {
+        let test_site = TestSite::new("create-section", "");
+        test_site.write(&[("_data/archetypes/notes.md", "---\n---\n")]);
+        let site = test_site.root.clone();
+
+        for kind in &["../../notes", "notes/..", "/tmp/notes", "", "."] {
+            let result = create(site.clone(), kind, Some(String::from("Escaped")));
+            assert!(matches!(result, Err(Error::Output { .. })), "{}", kind);
+        }
+
+        let result = create(site.clone(), "notes", Some(String::from("!!!")));
+        assert!(matches!(result, Err(Error::Slug { .. })));
+        assert!(!site.join("content/notes/.md").exists());
+
+        test_site.write(&[(
+            "_data/archetypes/notes.v2.md",
+            "---
+---
+",
+        )]);
+        let path = create(site.clone(), "notes.v2", Some(String::from("Dotted")))
+            .expect("creates an item of a kind with a dot in it");
+        assert!(path.ends_with("content/notes.v2/dotted.md"));
+    }
+
+    #[test]
+    fn rejects_invalid_front_matter() {
+        let path = Path::new("_data/archetypes/notes.md");
+        let date = DateTime::parse_from_rfc3339("2020-04-29T06:34:00-06:00").unwrap();
+        assert!(front_matter(path, "- not\n- a mapping\n", None, &date).is_err());
+
+        let header = front_matter(path, "tags: [fine]\n", None, &date).unwrap();
+        assert_eq!(header, "date: 2020-04-29T06:34:00-06:00\ntags:\n- fine\n");
+        assert!(Metadata::validate(path, &header).is_ok());
+        assert!(Metadata::validate(path, "featured: not a bool\ntitle: Oops\n").is_err());
+    }
+}
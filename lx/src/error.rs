@@ -0,0 +1,444 @@
+//! Everything which can go wrong while building a site, with enough detail
+//! (the file, and where possible the line and column) to find and fix it.
+
+use std::collections::BTreeMap;
+use std::fmt::{self, Display, Formatter};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum Error {
+    /// Reading or writing a file (or directory) failed.
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    /// The site config or a data cascade file is invalid.
+    Config {
+        path: PathBuf,
+        location: Option<Location>,
+        message: String,
+    },
+
+    /// An item's front matter is missing, malformed, or breaks a rule of one of
+    /// the site's taxonomies.
+    FrontMatter {
+        path: PathBuf,
+        location: Option<Location>,
+        message: String,
+    },
+
+    /// An item is missing a field it must have, e.g. a `title` or `date`.
+    MissingField { path: PathBuf, field: String },
+
+    /// An item has no `permalink`, and its path cannot be turned into a slug.
+    Slug { path: PathBuf, message: String },
+
+    /// A layout could not be loaded, or could not render some output.
+    Template {
+        /// The layout, or the layouts directory if they could not be loaded.
+        path: PathBuf,
+        /// What the layout was rendering, if anything.
+        output: Option<PathBuf>,
+        message: String,
+    },
+
+    /// A rule in the redirects file is malformed, or does not fit the site.
+    Redirect {
+        path: PathBuf,
+        location: Option<Location>,
+        message: String,
+    },
+
+    /// A Sass stylesheet could not be compiled.
+    Style {
+        /// The file with the error, which may be a partial the stylesheet uses.
+        path: PathBuf,
+        location: Option<Location>,
+        message: String,
+    },
+
+    /// One of the site's syntax definitions could not be loaded.
+    Syntax { path: PathBuf, message: String },
+
+    /// One of the site's syntax highlighting themes could not be loaded.
+    Theme { path: PathBuf, message: String },
+
+    /// More than one item (or generated page, e.g. a feed or a taxonomy page)
+    /// would write the same output file.
+    Collision {
+        /// The output file.
+        path: PathBuf,
+        /// Everything which would write it, e.g. the source paths of items.
+        sources: Vec<String>,
+    },
+
+    /// Output other than a rendered layout (e.g. a feed) could not be produced.
+    Output { path: PathBuf, message: String },
+
+    /// A file lx would create is already there, e.g. when scaffolding a site.
+    Exists { path: PathBuf, message: String },
+
+    /// The site could not be watched for changes.
+    Watch { path: PathBuf, message: String },
+
+    /// The local server could not start.
+    Serve { address: String, message: String },
+}
+
+/// A (1-based) position in a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Error {
+    /// The file the error is in (or about), if there is one.
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Error::Io { path, .. }
+            | Error::Config { path, .. }
+            | Error::FrontMatter { path, .. }
+            | Error::MissingField { path, .. }
+            | Error::Slug { path, .. }
+            | Error::Template { path, .. }
+            | Error::Redirect { path, .. }
+            | Error::Style { path, .. }
+            | Error::Syntax { path, .. }
+            | Error::Theme { path, .. }
+            | Error::Collision { path, .. }
+            | Error::Output { path, .. }
+            | Error::Exists { path, .. }
+            | Error::Watch { path, .. } => Some(path),
+            Error::Serve { .. } => None,
+        }
+    }
+
+    /// Set the output a template error occurred while rendering.
+    pub(crate) fn rendering(self, output: &Path) -> Error {
+        match self {
+            Error::Template { path, message, .. } => Error::Template {
+                path,
+                output: Some(output.to_owned()),
+                message,
+            },
+            other => other,
+        }
+    }
+
+    /// Describe the error without naming the file it is in.
+    fn describe(&self) -> String {
+        match self {
+            Error::Io { source, .. } => source.to_string(),
+            Error::Config {
+                location, message, ..
+            } => format!("{}invalid config: {}", at(location), message),
+            Error::FrontMatter {
+                location, message, ..
+            } => format!("{}invalid front matter: {}", at(location), message),
+            Error::MissingField { field, .. } => format!("missing required field: {}", field),
+            Error::Slug { message, .. } => format!("could not derive a slug: {}", message),
+            Error::Template {
+                output: Some(output),
+                message,
+                ..
+            } => format!("could not render '{}': {}", output.display(), message),
+            Error::Template {
+                output: None,
+                message,
+                ..
+            } => message.clone(),
+            Error::Redirect {
+                location, message, ..
+            } => format!("{}invalid redirect: {}", at(location), message),
+            Error::Style {
+                location, message, ..
+            } => format!("{}could not compile: {}", at(location), message),
+            Error::Syntax { message, .. } => format!("invalid syntax definition: {}", message),
+            Error::Theme { message, .. } => format!("invalid theme: {}", message),
+            Error::Collision { sources, .. } => {
+                format!("written by more than one source:\n{}", sources.join("\n"))
+            }
+            Error::Output { message, .. } | Error::Watch { message, .. } => message.clone(),
+            Error::Exists { message, .. } => format!("already exists ({})", message),
+            Error::Serve { address, message } => {
+                format!("could not serve at '{}': {}", address, message)
+            }
+        }
+    }
+
+    pub(crate) fn io(path: &Path, source: std::io::Error) -> Error {
+        Error::Io {
+            path: path.to_owned(),
+            source,
+        }
+    }
+
+    /// A site config which failed to parse.
+    pub(crate) fn config(path: &Path, error: json5::Error) -> Error {
+        let json5::Error::Message { msg, location } = error;
+        Error::Config {
+            path: path.to_owned(),
+            location: location.map(|location| Location {
+                line: location.line,
+                column: location.column,
+            }),
+            message: msg,
+        }
+    }
+
+    /// A Sass stylesheet (the entry point at `path`, or one of the files it
+    /// uses) which failed to compile.
+    pub(crate) fn style(path: &Path, error: grass::Error) -> Error {
+        match error.kind() {
+            grass::ErrorKind::ParseError { message, loc, .. } => Error::Style {
+                path: PathBuf::from(loc.file.name()),
+                location: Some(Location {
+                    line: loc.begin.line + 1,
+                    column: loc.begin.column + 1,
+                }),
+                message,
+            },
+            grass::ErrorKind::IoError(e) => Error::Style {
+                path: path.to_owned(),
+                location: None,
+                message: e.to_string(),
+            },
+            grass::ErrorKind::FromUtf8Error(message) => Error::Style {
+                path: path.to_owned(),
+                location: None,
+                message,
+            },
+            _ => Error::Style {
+                path: path.to_owned(),
+                location: None,
+                message: String::from("unknown error"),
+            },
+        }
+    }
+
+    /// A data cascade file which failed to parse.
+    pub(crate) fn cascade(path: &Path, error: serde_yaml::Error) -> Error {
+        Error::Config {
+            path: path.to_owned(),
+            location: Location::of_yaml(&error),
+            message: yaml_message(&error),
+        }
+    }
+
+    /// Front matter which failed to parse.
+    pub(crate) fn front_matter(path: &Path, error: serde_yaml::Error) -> Error {
+        Error::FrontMatter {
+            path: path.to_owned(),
+            location: Location::of_yaml(&error),
+            message: yaml_message(&error),
+        }
+    }
+
+    /// Front matter which failed to deserialize after resolving the data
+    /// cascade, where a line and column would point into the merged data rather
+    /// than into the file, so the message says where it came from instead.
+    pub(crate) fn resolved_front_matter(path: &Path, error: serde_yaml::Error) -> Error {
+        Error::invalid_front_matter(
+            path,
+            format!(
+                "{} (after merging in the data cascade)",
+                yaml_message(&error)
+            ),
+        )
+    }
+
+    /// Front matter which parsed, but is not valid for some other reason.
+    pub(crate) fn invalid_front_matter<S: Into<String>>(path: &Path, message: S) -> Error {
+        Error::FrontMatter {
+            path: path.to_owned(),
+            location: None,
+            message: message.into(),
+        }
+    }
+}
+
+fn at(location: &Option<Location>) -> String {
+    match location {
+        Some(Location { line, column }) => format!("line {}, column {}: ", line, column),
+        None => String::new(),
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.path() {
+            Some(path) => write!(f, "{}: {}", path.display(), self.describe()),
+            None => write!(f, "{}", self.describe()),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl Location {
+    fn of_yaml(error: &serde_yaml::Error) -> Option<Location> {
+        error.location().map(|location| Location {
+            line: location.line(),
+            column: location.column(),
+        })
+    }
+}
+
+/// serde_yaml includes the location in its messages, but the location is
+/// reported separately here.
+fn yaml_message(error: &serde_yaml::Error) -> String {
+    let message = error.to_string();
+    match Location::of_yaml(error) {
+        Some(Location { line, column }) => {
+            message.replacen(&format!(" at line {} column {}", line, column), "", 1)
+        }
+        None => message,
+    }
+}
+
+/// Every error from a build, grouped by the file each is in so that a report
+/// reads like a to-do list.
+#[derive(Debug)]
+pub struct Report(Vec<Error>);
+
+impl Report {
+    pub fn new(errors: Vec<Error>) -> Report {
+        Report(errors)
+    }
+
+    pub fn errors(&self) -> &[Error] {
+        &self.0
+    }
+
+    pub fn into_errors(self) -> Vec<Error> {
+        self.0
+    }
+}
+
+impl From<Error> for Report {
+    fn from(error: Error) -> Report {
+        Report(vec![error])
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut by_file: BTreeMap<Option<&Path>, Vec<&Error>> = BTreeMap::new();
+        for error in &self.0 {
+            by_file.entry(error.path()).or_default().push(error);
+        }
+
+        let count = self.0.len();
+        writeln!(
+            f,
+            "{} {} in {} {}:",
+            count,
+            if count == 1 { "error" } else { "errors" },
+            by_file.len(),
+            if by_file.len() == 1 { "file" } else { "files" },
+        )?;
+
+        for (path, errors) in by_file {
+            match path {
+                Some(path) => writeln!(f, "\n{}", path.display())?,
+                None => writeln!(f, "\n(no file)")?,
+            }
+            for error in errors {
+                let description = error.describe();
+                let mut lines = description.lines();
+                if let Some(first) = lines.next() {
+                    writeln!(f, "  - {}", first)?;
+                }
+                for line in lines {
+                    writeln!(f, "    {}", line)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for Report {}
+
+#[cfg(test)]
+mod tests {
+    use serde_yaml::Value;
+
+    use super::*;
+
+    #[test]
+    fn locates_parse_errors() {
+        let path = Path::new("content/broken.md");
+        let yaml_error =
+            serde_yaml::from_str::<Value>("title: Fine\ndate: [unclosed\n").unwrap_err();
+        match Error::front_matter(path, yaml_error) {
+            Error::FrontMatter {
+                location: Some(location),
+                message,
+                ..
+            } => {
+                assert_eq!(location.line, 3);
+                assert!(message.starts_with("did not find expected"), "{}", message);
+                assert!(!message.contains("line 3"), "{}", message);
+            }
+            other => panic!("expected a located front matter error, got {:?}", other),
+        }
+
+        let path = Path::new("_data/config.json5");
+        let json5_error = json5::from_str::<serde_json::Value>("{\n  url: ,\n}").unwrap_err();
+        match Error::config(path, json5_error) {
+            Error::Config {
+                location: Some(Location { line, column }),
+                ..
+            } => assert_eq!((line, column), (2, 8)),
+            other => panic!("expected a located config error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn reports_errors_grouped_by_file() {
+        let report = Report::new(vec![
+            Error::MissingField {
+                path: PathBuf::from("content/b.md"),
+                field: String::from("a title or a date (or both)"),
+            },
+            Error::FrontMatter {
+                path: PathBuf::from("content/a.md"),
+                location: Some(Location { line: 2, column: 7 }),
+                message: String::from("invalid type"),
+            },
+            Error::Template {
+                path: PathBuf::from("_layouts/base.html"),
+                output: Some(PathBuf::from("public/a.html")),
+                message: String::from("Failed to render 'base.html'\nVariable `oops` not found"),
+            },
+            Error::invalid_front_matter(Path::new("content/a.md"), "too many tags"),
+        ]);
+
+        assert_eq!(
+            report.to_string(),
+            "4 errors in 3 files:\n\
+             \n\
+             _layouts/base.html\n  \
+             - could not render 'public/a.html': Failed to render 'base.html'\n    \
+             Variable `oops` not found\n\
+             \n\
+             content/a.md\n  \
+             - line 2, column 7: invalid front matter: invalid type\n  \
+             - invalid front matter: too many tags\n\
+             \n\
+             content/b.md\n  \
+             - missing required field: a title or a date (or both)\n"
+        );
+    }
+}
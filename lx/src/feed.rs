@@ -1,52 +1,505 @@
+mod atom;
 mod json;
+mod rss;
 
-use std::convert::TryFrom;
+use std::path::{Path, PathBuf};
 
-use lx_json_feed::{AuthorOptions, JSONFeed};
+use chrono::{DateTime, FixedOffset};
 
-use crate::{config::Config, page::Page};
+use crate::{
+    config::{Config, Engine},
+    error::Error,
+    page::Page,
+};
+
+fn in_section(page: &Page, section: &str) -> bool {
+    page.metadata.section.as_deref() == Some(section)
+}
 
 /// Required resources for a `Feed`.
 pub(crate) struct Feed<'a> {
     /// Every feed has its own title.
     title: String,
 
+    /// Where the feed lives, relative to the root of the site: empty for the
+    /// site-wide feed, or e.g. `journal` for a section feed.
+    dir: String,
+
+    /// The formats to render the feed in.
+    engines: Vec<Engine>,
+
     /// Feeds also need read access to the site config to be able to render the
     /// full set of data specified for Atom, JSON, or RSS.
     site_config: &'a Config,
 
-    /// The set of items to render in the feed. A read-only slice because I will
-    /// never actually need to *write* to these. I just need the parsed metadata
-    /// and rendered HTML contents of the page, to render into the template.
-    items: &'a [Page],
+    /// The set of items to render in the feed, newest first. Read-only because
+    /// I will never actually need to *write* to these. I just need the parsed
+    /// metadata and rendered HTML contents of the page, to render into the
+    /// template.
+    items: Vec<&'a Page>,
 }
 
 impl<'a> Feed<'a> {
-    pub(crate) fn _new(title: String, site_config: &'a Config, items: &'a [Page]) -> Feed<'a> {
+    /// Only dated items can appear in a feed: an item with only a title is a
+    /// standalone page, not an entry in the stream of the site's content.
+    pub(crate) fn new(
+        title: String,
+        dir: String,
+        engines: Vec<Engine>,
+        site_config: &'a Config,
+        items: impl IntoIterator<Item = &'a Page>,
+    ) -> Feed<'a> {
+        let mut items: Vec<&Page> = items
+            .into_iter()
+            .filter(|page| page.metadata.date().is_some())
+            .collect();
+        items.sort_by(|a, b| b.metadata.date().cmp(&a.metadata.date()));
+
         Feed {
             title,
+            dir,
+            engines,
             site_config,
             items,
         }
     }
+
+    /// Every feed the site config asks for: the site-wide feed, plus one for
+    /// each configured section.
+    pub(crate) fn all_for_site(config: &'a Config, pages: &'a [Page]) -> Vec<Feed<'a>> {
+        let site_title = config
+            .feeds
+            .title
+            .clone()
+            .unwrap_or_else(|| config.title.normal.clone());
+
+        let engines = &config.feeds.engines;
+        let mut feeds = vec![Feed::new(
+            site_title,
+            String::new(),
+            engines.clone(),
+            config,
+            pages,
+        )];
+        feeds.extend(config.feeds.sections.iter().map(|section_feed| {
+            let in_section = pages
+                .iter()
+                .filter(move |page| in_section(page, &section_feed.section));
+
+            Feed::new(
+                section_feed.title.clone(),
+                section_feed.section.clone(),
+                section_feed
+                    .engines
+                    .clone()
+                    .unwrap_or_else(|| engines.clone()),
+                config,
+                in_section,
+            )
+        }));
+
+        feeds
+    }
+
+    /// Whether `page` belongs in this feed, if this is the site-wide feed or a
+    /// section feed. Used to decide which feeds to rewrite after `page` changes.
+    pub(crate) fn covers(&self, page: &Page) -> bool {
+        page.metadata.date().is_some() && (self.dir.is_empty() || in_section(page, &self.dir))
+    }
+
+    /// What writes the feed, for reporting collisions.
+    pub(crate) fn describe(&self) -> String {
+        if self.dir.is_empty() {
+            String::from("the site feed")
+        } else {
+            format!("the '{}' feed", self.dir)
+        }
+    }
+
+    /// The URL of the HTML page the feed describes.
+    fn home_page_url(&self) -> String {
+        let base = self.site_config.url.trim_end_matches('/');
+        if self.dir.is_empty() {
+            format!("{}/", base)
+        } else {
+            format!("{}/{}/", base, self.dir)
+        }
+    }
+
+    /// The URL of the feed file itself.
+    fn url_for(&self, file_name: &str) -> String {
+        self.home_page_url() + file_name
+    }
+
+    /// The date of the most recent change to any item in the feed.
+    fn updated(&self) -> Option<DateTime<FixedOffset>> {
+        self.items
+            .iter()
+            .filter_map(|page| {
+                page.metadata
+                    .updated
+                    .or_else(|| page.metadata.date().copied())
+            })
+            .max()
+    }
+
+    /// Every file the feed writes under `output_dir`: one for each engine.
+    pub(crate) fn output_paths(&self, output_dir: &Path) -> Vec<PathBuf> {
+        let dir = output_dir.join(&self.dir);
+        self.engines
+            .iter()
+            .map(|engine| dir.join(file_name(engine)))
+            .collect()
+    }
+
+    /// Write the feed into its directory under `output_dir`, once for each of
+    /// its engines.
+    pub(crate) fn write(&self, output_dir: &Path) -> Result<(), Error> {
+        let dir = output_dir.join(&self.dir);
+        std::fs::create_dir_all(&dir).map_err(|e| Error::io(&dir, e))?;
+
+        for engine in &self.engines {
+            let rendered = match engine {
+                Engine::Json => json::render(self),
+                Engine::Rss => rss::render(self),
+                Engine::Atom => atom::render(self),
+            };
+
+            let path = dir.join(file_name(engine));
+            let contents = rendered.map_err(|message| Error::Output {
+                path: path.clone(),
+                message,
+            })?;
+            std::fs::write(&path, contents).map_err(|e| Error::io(&path, e))?;
+        }
+
+        Ok(())
+    }
 }
 
-impl<'a> TryFrom<Feed<'a>> for JSONFeed {
-    type Error = String;
+fn file_name(engine: &Engine) -> &'static str {
+    match engine {
+        Engine::Json => json::FILE_NAME,
+        Engine::Rss => rss::FILE_NAME,
+        Engine::Atom => atom::FILE_NAME,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::rss::validation::Validate;
+
+    use super::*;
+    use crate::test_site::{config, pages, TestSite};
+
+    const SOURCES: &[(&str, &str)] = &[
+        (
+            "journal/older.md",
+            "---\ntitle: Older & Wiser\ndate: 2019-11-30T09:00:00-07:00\ntags: [rust]\n---\nHello <em>there</em>, ]]> friends.",
+        ),
+        (
+            "journal/newer.md",
+            "---\ntitle: Newer\ndate: 2020-01-02T10:30:00-07:00\nupdated: 2020-01-03T12:00:00-07:00\nsummary: A summary.\n---\nSome *Markdown*.",
+        ),
+        ("notes/untitled.md", "---\ndate: 2020-02-01T08:00:00-07:00\n---\nA note."),
+        ("about.md", "---\ntitle: About\n---\nNot in any feed."),
+    ];
+
+    fn feed<'a>(config: &'a Config, pages: &'a [Page]) -> Feed<'a> {
+        Feed::new(
+            String::from("Example"),
+            String::new(),
+            vec![Engine::Json, Engine::Rss, Engine::Atom],
+            config,
+            pages,
+        )
+    }
+
+    #[test]
+    fn only_dated_items_newest_first() {
+        let config = config("");
+        let pages = pages(&config, SOURCES);
+        let feed = feed(&config, &pages);
+
+        let slugs: Vec<&str> = feed
+            .items
+            .iter()
+            .map(|page| page.metadata.slug.as_str())
+            .collect();
+        assert_eq!(
+            slugs,
+            vec!["notes/untitled", "journal/newer", "journal/older"]
+        );
+    }
+
+    #[test]
+    fn section_feeds_only_include_their_section() {
+        let mut config = config("");
+        config.feeds.sections = vec![crate::config::SectionFeed {
+            section: String::from("journal"),
+            title: String::from("Journal"),
+            engines: Some(vec![Engine::Atom]),
+        }];
+        let pages = pages(&config, SOURCES);
+
+        let feeds = Feed::all_for_site(&config, &pages);
+        assert_eq!(feeds.len(), 2);
+        assert_eq!(feeds[1].dir, "journal");
+        assert_eq!(feeds[1].engines, vec![Engine::Atom]);
+        assert_eq!(feeds[1].items.len(), 2);
+        assert_eq!(
+            feeds[1].url_for(atom::FILE_NAME),
+            "https://example.com/journal/atom.xml"
+        );
+    }
+
+    #[test]
+    fn a_site_feed_and_a_feed_per_section() {
+        let default_config = config("");
+        let default_pages = pages(&default_config, SOURCES);
+        let feeds = Feed::all_for_site(&default_config, &default_pages);
+        assert_eq!(feeds.len(), 1);
+        assert_eq!(feeds[0].title, "Example", "defaults to the site title");
+
+        let config = config(
+            r#"feeds: {
+                title: "Everything",
+                sections: [
+                    { section: "journal", title: "Journal" },
+                    { section: "notes", title: "Notes" },
+                ],
+            }"#,
+        );
+        let pages = pages(&config, SOURCES);
+        let feeds = Feed::all_for_site(&config, &pages);
+        let summary: Vec<(&str, &str, usize)> = feeds
+            .iter()
+            .map(|feed| (feed.title.as_str(), feed.dir.as_str(), feed.items.len()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("Everything", "", 3),
+                ("Journal", "journal", 2),
+                ("Notes", "notes", 1)
+            ]
+        );
+
+        let site = TestSite::empty("feed-sections");
+        for feed in &feeds {
+            feed.write(&site.root).expect("writes the feed");
+        }
+        let journal = std::fs::read_to_string(site.root.join("journal/feed.json")).unwrap();
+        let journal: serde_json::Value = serde_json::from_str(&journal).unwrap();
+        assert_eq!(journal["title"], "Journal");
+        assert_eq!(journal["home_page_url"], "https://example.com/journal/");
+        assert_eq!(journal["feed_url"], "https://example.com/journal/feed.json");
+        assert_eq!(journal["items"][0]["title"], "Newer");
+        assert!(site.root.join("notes/feed.json").is_file());
+        assert!(site.root.join("feed.json").is_file());
+    }
+
+    #[test]
+    fn valid_rss() {
+        let config = config("");
+        let pages = pages(&config, SOURCES);
+        let rendered = rss::render(&feed(&config, &pages)).expect("renders RSS");
+
+        let channel = ::rss::Channel::read_from(rendered.as_bytes()).expect("RSS is well-formed");
+        channel.validate().expect("RSS is valid");
 
-    fn try_from(feed: Feed<'a>) -> Result<Self, Self::Error> {
-        let items = feed.items.iter().map(|page| page.into()).collect();
+        assert_eq!(channel.description(), "Examples & <such>");
+        assert_eq!(
+            channel.managing_editor(),
+            Some("alex@example.com (Alex Example)")
+        );
+
+        let self_link = &channel.atom_ext().expect("has Atom extension").links()[0];
+        assert_eq!(self_link.rel(), "self");
+        assert_eq!(self_link.href(), "https://example.com/feed.xml");
+
+        let items = channel.items();
+        assert_eq!(items.len(), 3);
+        assert_eq!(items[0].title(), None);
+
+        let older = &items[2];
+        assert_eq!(older.title(), Some("Older & Wiser"));
+        assert_eq!(older.link(), Some("https://example.com/journal/older"));
+        assert_eq!(older.pub_date(), Some("Sat, 30 Nov 2019 09:00:00 -0700"));
+        assert_eq!(
+            older.description().map(str::trim),
+            Some("<p>Hello <em>there</em>, ]]&gt; friends.</p>")
+        );
+        assert_eq!(older.categories()[0].name(), "rust");
+    }
+
+    /// Check the Atom feed in `xml` against the requirements of RFC 4287 (the
+    /// `atom_syndication` crate parses missing elements as empty ones, so this
+    /// checks the XML itself where it matters).
+    fn validate_atom(xml: &str) -> Result<::atom_syndication::Feed, String> {
+        let feed = ::atom_syndication::Feed::read_from(xml.as_bytes())
+            .map_err(|e| format!("not well-formed: {}", e))?;
+        let is_iri = |id: &str| id.starts_with("https://") || id.starts_with("http://");
+
+        // 4.1.1: a feed has exactly one id, title, and updated, at least one
+        // author unless every entry has one, and should link to itself.
+        let head = &xml[..xml.find("<entry>").unwrap_or(xml.len())];
+        for element in &["<id>", "<title", "<updated>"] {
+            if head.matches(element).count() != 1 {
+                return Err(format!("the feed must have exactly one {}", element));
+            }
+        }
+        if !is_iri(&feed.id) {
+            return Err(format!("the feed id '{}' is not an IRI", feed.id));
+        }
+        if feed.authors.is_empty() && feed.entries.iter().any(|entry| entry.authors.is_empty()) {
+            return Err(String::from("the feed or every entry must have an author"));
+        }
+        if !feed
+            .links
+            .iter()
+            .any(|link| link.rel == "self" && link.href == feed.id)
+        {
+            return Err(String::from("the feed must link to itself"));
+        }
 
-        // TODO: needs the info for the *feed* URL.
-        let feed = JSONFeed::builder(&feed.title, items)
-            .with_author(&AuthorOptions {
-                name: Some(&feed.site_config.author.name),
-                url: None,
-                avatar: None,
-            })?
-            .with_description(&feed.site_config.description)
-            .build();
+        // 4.1.2: so does every entry, which also needs either content or an
+        // alternate link, and ids must be unique.
+        for (entry, xml) in feed.entries.iter().zip(xml.split("<entry>").skip(1)) {
+            for element in &["<id>", "<title", "<updated>"] {
+                if xml.matches(element).count() != 1 {
+                    return Err(format!("'{}' must have exactly one {}", entry.id, element));
+                }
+            }
+            if !is_iri(&entry.id) {
+                return Err(format!("the entry id '{}' is not an IRI", entry.id));
+            }
+            if entry.content.is_none() && !entry.links.iter().any(|link| link.rel == "alternate") {
+                return Err(format!("'{}' needs content or an alternate link", entry.id));
+            }
+            if feed
+                .entries
+                .iter()
+                .filter(|other| other.id == entry.id)
+                .count()
+                > 1
+            {
+                return Err(format!("the entry id '{}' is not unique", entry.id));
+            }
+        }
 
         Ok(feed)
     }
+
+    #[test]
+    fn valid_atom() {
+        let config = config("");
+        let pages = pages(&config, SOURCES);
+        let rendered = atom::render(&feed(&config, &pages)).expect("renders Atom");
+
+        let atom_feed = validate_atom(&rendered).expect("Atom is valid");
+        assert_eq!(atom_feed.entries.len(), 3);
+        assert_eq!(atom_feed.title.value, "Example");
+
+        assert_eq!(atom_feed.id, "https://example.com/atom.xml");
+        assert_eq!(
+            atom_feed.updated,
+            DateTime::parse_from_rfc3339("2020-02-01T08:00:00-07:00").unwrap()
+        );
+        assert_eq!(atom_feed.links[0].rel, "self");
+        assert_eq!(atom_feed.links[0].href, "https://example.com/atom.xml");
+        assert_eq!(atom_feed.authors[0].name, "Alex Example");
+        assert_eq!(
+            atom_feed.authors[0].email.as_deref(),
+            Some("alex@example.com")
+        );
+
+        let newer = &atom_feed.entries[1];
+        assert_eq!(newer.id, "https://example.com/journal/newer");
+        assert_eq!(newer.title.value, "Newer");
+        assert_eq!(
+            newer.updated,
+            DateTime::parse_from_rfc3339("2020-01-03T12:00:00-07:00").unwrap()
+        );
+        assert_eq!(
+            newer.published,
+            DateTime::parse_from_rfc3339("2020-01-02T10:30:00-07:00").ok()
+        );
+        assert_eq!(newer.summary.as_ref().unwrap().value, "A summary.");
+
+        let content = newer.content.as_ref().expect("has content");
+        assert_eq!(content.content_type.as_deref(), Some("html"));
+        assert_eq!(
+            content.value.as_deref(),
+            Some("<p>Some <em>Markdown</em>.</p>\n")
+        );
+
+        // The raw XML must escape the HTML rather than embedding it as markup.
+        assert!(rendered.contains("&lt;p&gt;Some &lt;em&gt;Markdown&lt;/em&gt;.&lt;/p&gt;"));
+
+        // An untitled note still gets an (empty) title, as Atom requires.
+        assert!(rendered.contains("<title></title>") || rendered.contains("<title/>"));
+        for broken in &[
+            rendered.replacen("<updated>", "<published>", 1).replacen(
+                "</updated>",
+                "</published>",
+                1,
+            ),
+            rendered.replace("rel=\"self\"", "rel=\"related\""),
+            rendered
+                .replace("<author>", "<contributor>")
+                .replace("</author>", "</contributor>"),
+            rendered.replacen("<id>https://example.com/notes/untitled</id>", "", 1),
+        ] {
+            assert!(validate_atom(broken).is_err(), "{}", broken);
+        }
+    }
+
+    #[test]
+    fn an_empty_atom_feed_is_dated_by_the_epoch() {
+        let config = config("");
+        let rendered = atom::render(&feed(&config, &[])).expect("renders Atom");
+
+        let atom_feed = validate_atom(&rendered).expect("Atom is valid");
+        assert!(atom_feed.entries.is_empty());
+        assert_eq!(
+            atom_feed.updated,
+            DateTime::parse_from_rfc3339("1970-01-01T00:00:00Z").unwrap()
+        );
+        assert_eq!(rendered, atom::render(&feed(&config, &[])).unwrap());
+    }
+
+    #[test]
+    fn valid_json() {
+        let config = config("");
+        let pages = pages(&config, SOURCES);
+        let rendered = json::render(&feed(&config, &pages)).expect("renders JSON");
+
+        let json: serde_json::Value = serde_json::from_str(&rendered).expect("JSON is valid");
+        assert_eq!(json["version"], "https://jsonfeed.org/version/1.1");
+        assert_eq!(json["feed_url"], "https://example.com/feed.json");
+        assert_eq!(json["home_page_url"], "https://example.com/");
+        assert_eq!(
+            json["items"][1]["date_modified"],
+            "2020-01-03T12:00:00-07:00"
+        );
+        assert_eq!(json["items"][2]["tags"][0], "rust");
+    }
+
+    #[test]
+    fn writes_every_engine() {
+        let config = config("");
+        let pages = pages(&config, SOURCES);
+        let site = TestSite::empty("feed-write");
+        let output = site.root.join("output");
+
+        let feed = feed(&config, &pages);
+        feed.write(&output).expect("writes feeds");
+        for file_name in &[json::FILE_NAME, rss::FILE_NAME, atom::FILE_NAME] {
+            assert!(output.join(file_name).is_file(), "missing {}", file_name);
+        }
+        let paths = feed.output_paths(&output);
+        assert_eq!(paths.len(), 3);
+        assert!(paths.iter().all(|path| path.is_file()));
+    }
 }
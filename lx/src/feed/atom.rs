@@ -0,0 +1,102 @@
+//! [Atom 1.0](https://www.rfc-editor.org/rfc/rfc4287).
+
+use std::time::UNIX_EPOCH;
+
+use atom_syndication::{Category, Content, Entry, Generator, Link, Person, Text};
+use chrono::{DateTime, Utc};
+
+use super::Feed;
+use crate::config::Config;
+use crate::page::Page;
+
+pub(super) const FILE_NAME: &str = "atom.xml";
+
+pub(super) fn render(feed: &Feed) -> Result<String, String> {
+    let config = feed.site_config;
+    let feed_url = feed.url_for(FILE_NAME);
+
+    let entries = feed
+        .items
+        .iter()
+        .map(|page| entry(page, config))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // A feed with no items has nothing to date it by, so use the epoch rather
+    // than the time of the build, which would change the feed on every build.
+    let updated = feed
+        .updated()
+        .unwrap_or_else(|| DateTime::<Utc>::from(UNIX_EPOCH).into());
+
+    let atom_feed = atom_syndication::Feed {
+        title: Text::plain(feed.title.clone()),
+        id: feed_url.clone(),
+        updated,
+        authors: vec![author(config)],
+        generator: Some(Generator {
+            value: String::from("lx"),
+            ..Default::default()
+        }),
+        links: vec![
+            link(feed_url, "self", Some("application/atom+xml")),
+            link(feed.home_page_url(), "alternate", Some("text/html")),
+        ],
+        subtitle: Some(Text::plain(config.description.clone())),
+        entries,
+        ..Default::default()
+    };
+
+    Ok(atom_feed.to_string())
+}
+
+fn entry(page: &Page, config: &Config) -> Result<Entry, String> {
+    let url = page.url(config);
+    let metadata = &page.metadata;
+
+    let published = metadata.date().copied();
+    let updated = metadata
+        .updated
+        .or(published)
+        .ok_or_else(|| format!("'{}' has no date to use for Atom", metadata.slug))?;
+
+    Ok(Entry {
+        // Atom requires a title, but it may be empty, as for e.g. notes.
+        title: Text::plain(metadata.title().unwrap_or_default()),
+        id: url.clone(),
+        updated,
+        published,
+        authors: vec![author(config)],
+        links: vec![link(url, "alternate", Some("text/html"))],
+        summary: metadata.summary.clone().map(Text::plain),
+        content: Some(Content {
+            value: Some(page.contents.to_string()),
+            content_type: Some(String::from("html")),
+            ..Default::default()
+        }),
+        categories: metadata
+            .tags
+            .iter()
+            .map(|tag| Category {
+                term: tag.clone(),
+                ..Default::default()
+            })
+            .collect(),
+        ..Default::default()
+    })
+}
+
+fn link(href: String, rel: &str, mime_type: Option<&str>) -> Link {
+    Link {
+        href,
+        rel: String::from(rel),
+        mime_type: mime_type.map(String::from),
+        ..Default::default()
+    }
+}
+
+fn author(config: &Config) -> Person {
+    Person {
+        name: config.author.name.clone(),
+        email: Some(config.author.email.to_string()),
+        uri: Some(config.url.clone()),
+    }
+}
@@ -1 +1,65 @@
+use std::convert::TryFrom;
 
+use lx_json_feed::{AuthorOptions, FeedItem, JSONFeed};
+
+use super::Feed;
+use crate::config::Config;
+use crate::page::Page;
+
+pub(super) const FILE_NAME: &str = "feed.json";
+
+pub(super) fn render(feed: &Feed) -> Result<String, String> {
+    let json_feed = JSONFeed::try_from(feed)?;
+    serde_json::to_string_pretty(&json_feed).map_err(|e| e.to_string())
+}
+
+impl<'a> TryFrom<&Feed<'a>> for JSONFeed {
+    type Error = String;
+
+    fn try_from(feed: &Feed<'a>) -> Result<Self, Self::Error> {
+        let items = feed
+            .items
+            .iter()
+            .map(|page| feed_item(page, feed.site_config))
+            .collect();
+
+        let json_feed = JSONFeed::builder(&feed.title, items)
+            .with_home_page_url(&feed.home_page_url())
+            .with_feed_url(&feed.url_for(FILE_NAME))
+            .with_author(&AuthorOptions {
+                name: Some(&feed.site_config.author.name),
+                url: None,
+                avatar: None,
+            })?
+            .with_description(&feed.site_config.description)
+            .build();
+
+        Ok(json_feed)
+    }
+}
+
+fn feed_item(page: &Page, config: &Config) -> FeedItem {
+    let url = page.url(config);
+    let metadata = &page.metadata;
+
+    FeedItem {
+        id: url.clone(),
+        url: Some(url),
+        external_url: None,
+        title: metadata.title().map(String::from),
+        content_text: None,
+        content_html: Some(page.contents.to_string()),
+        summary: metadata.summary.clone(),
+        image: None,
+        banner_image: None,
+        date_published: metadata.date().map(|date| date.to_rfc3339()),
+        date_modified: metadata.updated.map(|updated| updated.to_rfc3339()),
+        author: None,
+        tags: if metadata.tags.is_empty() {
+            None
+        } else {
+            Some(metadata.tags.clone())
+        },
+        attachments: None,
+    }
+}
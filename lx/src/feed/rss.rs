@@ -0,0 +1,75 @@
+//! [RSS 2.0](https://www.rssboard.org/rss-specification).
+
+use rss::extension::atom::{AtomExtension, Link};
+use rss::{Category, Channel, Guid, Item};
+
+use super::Feed;
+use crate::config::Config;
+use crate::page::Page;
+
+pub(super) const FILE_NAME: &str = "feed.xml";
+
+pub(super) fn render(feed: &Feed) -> Result<String, String> {
+    let config = feed.site_config;
+
+    let self_link = Link {
+        href: feed.url_for(FILE_NAME),
+        rel: String::from("self"),
+        mime_type: Some(String::from("application/rss+xml")),
+        ..Default::default()
+    };
+
+    let mut atom_ext = AtomExtension::default();
+    atom_ext.set_links(vec![self_link]);
+
+    let mut channel = Channel::default();
+    channel.set_title(feed.title.clone());
+    channel.set_link(feed.home_page_url());
+    channel.set_description(config.description.clone());
+    channel.set_managing_editor(author(config));
+    channel.set_last_build_date(feed.updated().map(|updated| updated.to_rfc2822()));
+    channel.set_generator(Some(String::from("lx")));
+    channel.set_atom_ext(atom_ext);
+    channel.set_items(
+        feed.items
+            .iter()
+            .map(|page| item(page, config))
+            .collect::<Vec<_>>(),
+    );
+
+    Ok(channel.to_string())
+}
+
+fn item(page: &Page, config: &Config) -> Item {
+    let url = page.url(config);
+    let metadata = &page.metadata;
+
+    let mut guid = Guid::default();
+    guid.set_value(url.clone());
+    guid.set_permalink(true);
+
+    let mut item = Item::default();
+    item.set_title(metadata.title().map(String::from));
+    item.set_link(url);
+    item.set_guid(guid);
+    item.set_description(page.contents.to_string());
+    item.set_pub_date(metadata.date().map(|date| date.to_rfc2822()));
+    item.set_author(author(config));
+    item.set_categories(
+        metadata
+            .tags
+            .iter()
+            .map(|tag| {
+                let mut category = Category::default();
+                category.set_name(tag.clone());
+                category
+            })
+            .collect::<Vec<_>>(),
+    );
+    item
+}
+
+/// RSS requires an email address for authors, optionally followed by a name.
+fn author(config: &Config) -> String {
+    format!("{} ({})", config.author.email, config.author.name)
+}
@@ -1,5 +1,95 @@
-use std::path::PathBuf;
+//! Scaffold a new site which builds out of the box.
 
-pub fn init(_site_directory: PathBuf) -> Result<(), String> {
-    unimplemented!();
+use std::path::{Path, PathBuf};
+
+use crate::error::Error;
+
+const CONFIG: &str = include_str!("initializer/config.json5");
+const LAYOUT: &str = include_str!("initializer/base.html");
+const SAMPLE_POST: &str = include_str!("initializer/hello-world.md");
+
+/// Create the config, layout, and content for a new site in `site_directory`,
+/// creating the directory itself if need be. Refuses to overwrite any of those
+/// files unless `force` is set, in which case it overwrites only the files it
+/// would otherwise create.
+pub fn init(site_directory: PathBuf, force: bool) -> Result<(), Error> {
+    let date = chrono::Local::now()
+        .format("%Y-%m-%dT%H:%M:%S%:z")
+        .to_string();
+    let files = [
+        (
+            site_directory.join("_data/config.json5"),
+            String::from(CONFIG),
+        ),
+        (
+            site_directory.join("_layouts/base.html"),
+            String::from(LAYOUT),
+        ),
+        (
+            site_directory.join("content/hello-world.md"),
+            SAMPLE_POST.replace("{date}", &date),
+        ),
+    ];
+
+    if !force {
+        if let Some((path, _)) = files.iter().find(|(path, _)| path.exists()) {
+            return Err(Error::Exists {
+                path: path.clone(),
+                message: String::from("use --force to overwrite it"),
+            });
+        }
+    }
+
+    for (path, contents) in &files {
+        write(path, contents)?;
+    }
+
+    Ok(())
+}
+
+fn write(path: &Path, contents: &str) -> Result<(), Error> {
+    if let Some(containing_dir) = path.parent() {
+        std::fs::create_dir_all(containing_dir).map_err(|e| Error::io(path, e))?;
+    }
+    std::fs::write(path, contents).map_err(|e| Error::io(path, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_site::TestSite;
+
+    #[test]
+    fn initialized_site_builds() {
+        let dir = TestSite::empty("init");
+        let site = dir.root.join("site");
+
+        init(site.clone(), false).expect("initializes a new site");
+        crate::build(site.clone(), false).expect("the new site builds");
+        assert!(site.join("public/hello-world.html").exists());
+
+        assert!(
+            init(site.clone(), false).is_err(),
+            "does not overwrite an existing site"
+        );
+        init(site.clone(), true).expect("overwrites an existing site when forced");
+    }
+
+    #[test]
+    fn does_not_overwrite_any_file() {
+        let dir = TestSite::empty("init-existing");
+        dir.write(&[("_layouts/base.html", "{{ page.contents }}")]);
+
+        match init(dir.root.clone(), false) {
+            Err(Error::Exists { path, .. }) => {
+                assert_eq!(path, dir.root.join("_layouts/base.html"))
+            }
+            other => panic!("expected an existing file error, got {:?}", other),
+        }
+        assert!(!dir.root.join("_data/config.json5").exists());
+        assert_eq!(
+            std::fs::read_to_string(dir.root.join("_layouts/base.html")).unwrap(),
+            "{{ page.contents }}"
+        );
+    }
 }
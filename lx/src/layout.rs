@@ -0,0 +1,200 @@
+//! Render pages into the Tera layouts supplied by the site.
+
+use std::collections::HashMap;
+use std::error::Error as _;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde_derive::Serialize;
+use tera::{Context, Tera, Value};
+
+use crate::assets::Manifest;
+use crate::build::files_under;
+use crate::config::Config;
+use crate::error::Error;
+use crate::page::Page;
+
+/// The full set of layout templates for a site, loaded once per build.
+pub struct Layouts {
+    tera: Tera,
+    dir: PathBuf,
+}
+
+impl Layouts {
+    /// Load every template under `dir`. Templates are named by their path
+    /// relative to `dir`, so `<dir>/partials/nav.html` is `partials/nav.html`.
+    pub fn load(dir: &Path) -> Result<Layouts, Error> {
+        let templates = files_under(dir, "**/*")?
+            .into_iter()
+            .filter(|path| path.is_file())
+            .filter_map(|path| {
+                let name = path.strip_prefix(dir).ok()?.to_str()?.replace('\\', "/");
+                Some((path, Some(name)))
+            });
+
+        let mut tera = Tera::default();
+        tera.add_template_files(templates)
+            .map_err(|e| Error::Template {
+                path: dir.to_owned(),
+                output: None,
+                message: format!("could not load layouts:\n{}", describe(&e)),
+            })?;
+
+        Ok(Layouts {
+            tera,
+            dir: dir.to_owned(),
+        })
+    }
+
+    /// Make the site's assets available to templates as the `asset()`
+    /// function, which gives the URL path for an asset by its path in the
+    /// output, e.g. `{{ asset(path="styles/style.css") }}`, fingerprint and all.
+    /// It fails the render if there is no such asset.
+    pub(crate) fn register_assets(&mut self, manifest: Arc<Manifest>) {
+        self.tera.register_function(
+            "asset",
+            move |args: &HashMap<String, Value>| -> tera::Result<Value> {
+                let path = args
+                    .get("path")
+                    .and_then(Value::as_str)
+                    .ok_or_else(|| tera::Error::msg("`asset()` needs a `path`"))?;
+                manifest
+                    .resolve(path)
+                    .map(Value::from)
+                    .map_err(tera::Error::msg)
+            },
+        );
+    }
+
+    /// Render a page through the layout named in its metadata, exposing the
+    /// page (metadata and rendered contents) and the site config as `page` and
+    /// `config` respectively.
+    pub fn render_page(&self, page: &Page, config: &Config) -> Result<String, Error> {
+        let mut context = Context::new();
+        context.insert("page", page);
+        context.insert("config", config);
+
+        self.render(&page.metadata.layout, &context)
+            .map_err(|e| e.rendering(&page.output_path(config)))
+    }
+
+    pub(crate) fn render(&self, name: &str, context: &Context) -> Result<String, Error> {
+        self.tera
+            .render(name, context)
+            .map_err(|e| Error::Template {
+                path: self.dir.join(name),
+                output: None,
+                message: describe(&e),
+            })
+    }
+}
+
+/// A page as seen from a listing (e.g. a taxonomy term), with its full URL so
+/// that templates do not have to construct it.
+#[derive(Serialize)]
+pub(crate) struct PageLink<'a> {
+    url: String,
+    #[serde(flatten)]
+    page: &'a Page,
+}
+
+impl<'a> PageLink<'a> {
+    pub(crate) fn new(page: &'a Page, config: &Config) -> PageLink<'a> {
+        PageLink {
+            url: page.url(config),
+            page,
+        }
+    }
+}
+
+/// Tera nests the useful details (the line and column of a syntax error, or the
+/// variable which was missing while rendering) in the chain of `source()`s, so
+/// flatten the whole chain into a single message.
+fn describe(error: &tera::Error) -> String {
+    let mut description = error.to_string();
+    let mut source = error.source();
+    while let Some(cause) = source {
+        description.push_str(&format!("\n{}", cause));
+        source = cause.source();
+    }
+    description
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_site::{config, pages, TestSite};
+
+    #[test]
+    fn renders_pages_through_their_layouts() {
+        // Brackets mean something in a glob, so make sure they do not here.
+        let dir = TestSite::empty("layout-[render]");
+        dir.write(&[
+            (
+                "base.html",
+                "<title>{{ page.metadata.title }} | {{ config.title.normal }}</title>\
+                 {% block content %}{{ page.contents | safe }}{% endblock content %}",
+            ),
+            (
+                "essay.html",
+                "{% extends \"base.html\" %}{% block content %}<article>{{ page.contents | safe }}</article>{% endblock content %}",
+            ),
+        ]);
+        let layouts = Layouts::load(&dir.root).expect("loads the layouts");
+        let config = config("");
+        let pages = pages(
+            &config,
+            &[
+                ("plain.md", "---\ntitle: Plain\n---\nHello *there*."),
+                (
+                    "essay.md",
+                    "---\ntitle: Essay\nlayout: essay.html\n---\nAn essay.",
+                ),
+            ],
+        );
+
+        assert_eq!(
+            layouts.render_page(&pages[0], &config).unwrap(),
+            "<title>Plain | Example</title><p>Hello <em>there</em>.</p>\n"
+        );
+        assert_eq!(
+            layouts.render_page(&pages[1], &config).unwrap(),
+            "<title>Essay | Example</title><article><p>An essay.</p>\n</article>"
+        );
+    }
+
+    #[test]
+    fn reports_the_layout_and_what_it_was_rendering() {
+        let dir = TestSite::empty("layout-error");
+        dir.write(&[("base.html", "{{ page.metadata.nope.deeper }}")]);
+        let layouts = Layouts::load(&dir.root).expect("loads the layouts");
+        let config = config("");
+        let pages = pages(&config, &[("journal/entry.md", "---\ntitle: Entry\n---\n")]);
+
+        match layouts.render_page(&pages[0], &config) {
+            Err(Error::Template {
+                path,
+                output,
+                message,
+            }) => {
+                assert_eq!(path, dir.root.join("base.html"));
+                assert_eq!(output, Some(pages[0].output_path(&config)));
+                assert!(message.contains("nope"), "{}", message);
+            }
+            other => panic!("expected a template error, got {:?}", other),
+        }
+
+        dir.write(&[("broken.html", "{% if %}")]);
+        match Layouts::load(&dir.root) {
+            Err(Error::Template {
+                path,
+                output: None,
+                message,
+            }) => {
+                assert_eq!(path, dir.root);
+                assert!(message.contains("broken.html"), "{}", message);
+            }
+            other => panic!("expected a template error, got {:?}", other.map(|_| ())),
+        }
+    }
+}
@@ -1,10 +1,26 @@
 //! Generate web sites from Markdown content and YAML configuration.
 
+pub mod assets;
 pub mod build;
+pub mod cache;
 pub mod collection;
 pub mod config;
+pub mod create;
+pub mod error;
 pub mod feed;
+pub mod initializer;
+pub mod layout;
 pub mod markdown;
 pub mod page;
+pub mod redirects;
+pub mod serve;
+pub mod styles;
+pub mod syntaxes;
+pub mod taxonomy;
+#[cfg(test)]
+pub(crate) mod test_site;
+pub mod themes;
+pub mod watch;
 
 pub use build::build;
+pub use error::{Error, Report};
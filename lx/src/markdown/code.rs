@@ -0,0 +1,345 @@
+//! Attributes for fenced code blocks, given in braces after the language in the
+//! info string:
+//!
+//! ````markdown
+//! ```rust {linenos, hl_lines=[3, 5-7], title="src/main.rs"}
+//! ````
+//!
+//! - `linenos` numbers the lines.
+//! - `hl_lines` highlights the listed lines (counting from 1), each either a
+//!   single line or an inclusive range.
+//! - `title` captions the block, e.g. with the name of the file it comes from.
+//!
+//! Anything else is ignored with a warning, as is an attribute whose value does
+//! not make sense, so that a typo never fails a build.
+
+use std::ops::RangeInclusive;
+
+/// A fenced code block's language and attributes.
+#[derive(Debug, Default, PartialEq)]
+pub(super) struct CodeBlock {
+    /// The language, i.e. everything in the info string before the attributes.
+    pub(super) language: String,
+    line_numbers: bool,
+    highlighted: Vec<RangeInclusive<usize>>,
+    title: Option<String>,
+}
+
+impl CodeBlock {
+    /// Parse a fenced code block's info string, along with a warning for each
+    /// attribute which is not understood.
+    pub(super) fn parse(info: &str) -> (CodeBlock, Vec<String>) {
+        let mut warnings = Vec::new();
+        let (language, attributes) = match info.find('{') {
+            Some(start) => {
+                let rest = &info[start + 1..];
+                let attributes = match rest.trim_end().strip_suffix('}') {
+                    Some(attributes) => attributes,
+                    None => {
+                        warnings.push(format!(
+                            "code block attributes '{}' have no closing '}}'",
+                            rest
+                        ));
+                        rest
+                    }
+                };
+                (&info[..start], attributes)
+            }
+            None => (info, ""),
+        };
+
+        let mut block = CodeBlock {
+            language: language.trim().to_string(),
+            ..CodeBlock::default()
+        };
+        for attribute in split(attributes) {
+            let (name, value) = match attribute.find('=') {
+                Some(index) => (
+                    attribute[..index].trim(),
+                    Some(attribute[index + 1..].trim()),
+                ),
+                None => (attribute, None),
+            };
+
+            let problem = match (name, value) {
+                ("linenos", None) | ("linenos", Some("true")) => {
+                    block.line_numbers = true;
+                    None
+                }
+                ("linenos", Some("false")) => None,
+                ("linenos", Some(value)) => Some(format!(
+                    "`linenos` is either true or false, not '{}'",
+                    value
+                )),
+                ("hl_lines", Some(value)) => match lines(value) {
+                    Some(lines) => {
+                        block.highlighted = lines;
+                        None
+                    }
+                    None => Some(format!(
+                        "`hl_lines` must be a list of lines and ranges like [3, 5-7], not '{}'",
+                        value
+                    )),
+                },
+                ("title", Some(value)) => match string(value) {
+                    Some(title) if !title.is_empty() => {
+                        block.title = Some(title);
+                        None
+                    }
+                    _ => Some(format!(
+                        "`title` must be a non-empty string, not '{}'",
+                        value
+                    )),
+                },
+                ("hl_lines", None) | ("title", None) => Some(format!("`{}` needs a value", name)),
+                (name, _) => Some(format!("unknown code block attribute '{}'", name)),
+            };
+            warnings.extend(problem);
+        }
+
+        (block, warnings)
+    }
+
+    /// Wrap the block's `code` (already HTML, e.g. from syntax highlighting) in
+    /// `pre`, which opens the `<pre>` and `<code>` elements. With line numbers
+    /// or highlighted lines, each line of code gets an element of its own; with
+    /// a title, the whole block goes in a `<figure>` captioned with it.
+    pub(super) fn render(&self, pre: &str, code: &str) -> String {
+        let mut html = String::with_capacity(code.len() * 2);
+        if let Some(title) = &self.title {
+            html.push_str("<figure class=\"code-block\"><figcaption>");
+            html.push_str(&escape(title));
+            html.push_str("</figcaption>");
+        }
+
+        html.push_str(pre);
+        if self.line_numbers || !self.highlighted.is_empty() {
+            for (index, line) in lines_of(code).iter().enumerate() {
+                let number = index + 1;
+                if self.highlighted.iter().any(|range| range.contains(&number)) {
+                    html.push_str("<span class=\"line highlighted\">");
+                } else {
+                    html.push_str("<span class=\"line\">");
+                }
+                if self.line_numbers {
+                    html.push_str(&format!(
+                        "<span class=\"line-number\" aria-hidden=\"true\">{}</span>",
+                        number
+                    ));
+                }
+                html.push_str(line);
+                html.push_str("</span>\n");
+            }
+        } else {
+            html.push_str(code);
+        }
+        html.push_str("</code></pre>");
+
+        if self.title.is_some() {
+            html.push_str("</figure>");
+        }
+        html
+    }
+}
+
+/// Split attributes on the commas between them, but not those in a list or a
+/// string.
+fn split(attributes: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let (mut start, mut depth, mut in_string, mut escaped) = (0, 0, false, false);
+    for (index, c) in attributes.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '[' if !in_string => depth += 1,
+            ']' if !in_string => depth -= 1,
+            ',' if !in_string && depth == 0 => {
+                parts.push(&attributes[start..index]);
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&attributes[start..]);
+
+    parts
+        .into_iter()
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+        .collect()
+}
+
+/// Parse a list of lines like `[3, 5-7]` (or a single line or range).
+fn lines(value: &str) -> Option<Vec<RangeInclusive<usize>>> {
+    let value = value.trim();
+    let items = value
+        .strip_prefix('[')
+        .and_then(|value| value.strip_suffix(']'))
+        .unwrap_or(value);
+
+    items
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|item| !item.is_empty())
+        .map(|item| {
+            let (start, end) = match item.find('-') {
+                Some(index) => (&item[..index], &item[index + 1..]),
+                None => (item, item),
+            };
+            let (start, end) = (start.parse().ok()?, end.parse().ok()?);
+            if start == 0 || end < start {
+                None
+            } else {
+                Some(start..=end)
+            }
+        })
+        .collect::<Option<Vec<_>>>()
+        .filter(|lines| !lines.is_empty())
+}
+
+/// Parse a string, either in double quotes (with backslash escapes) or bare.
+fn string(value: &str) -> Option<String> {
+    let quoted = match value.strip_prefix('"') {
+        Some(quoted) => quoted.strip_suffix('"')?,
+        None => return Some(value.to_string()),
+    };
+
+    let mut string = String::with_capacity(quoted.len());
+    let mut chars = quoted.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => string.push(chars.next()?),
+            c => string.push(c),
+        }
+    }
+    Some(string)
+}
+
+/// Split highlighted HTML into lines, closing the `<span>`s still open at the
+/// end of each line and reopening them at the start of the next, so that each
+/// line can be wrapped in an element of its own.
+fn lines_of(html: &str) -> Vec<String> {
+    // Highlighting closes its outermost spans after the last newline, which
+    // would otherwise make a line of its own.
+    let unclosed = html.trim_end_matches("</span>");
+    let html = match unclosed.strip_suffix('\n') {
+        Some(code) => format!("{}{}", code, &html[unclosed.len()..]),
+        None => html.strip_suffix('\n').unwrap_or(html).to_string(),
+    };
+
+    let mut open: Vec<&str> = Vec::new();
+    let mut lines = Vec::new();
+    for line in html.split('\n') {
+        let mut wrapped = open.concat();
+
+        let mut rest = line;
+        while let Some(index) = rest.find('<') {
+            let end = rest[index..]
+                .find('>')
+                .map_or(rest.len(), |end| index + end + 1);
+            let tag = &rest[index..end];
+            if tag.starts_with("</") {
+                open.pop();
+            } else {
+                open.push(tag);
+            }
+            rest = &rest[end..];
+        }
+
+        wrapped.push_str(line);
+        wrapped.push_str(&"</span>".repeat(open.len()));
+        lines.push(wrapped);
+    }
+    lines
+}
+
+/// Escape text for use in HTML, including in attribute values.
+pub(super) fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_attributes() {
+        let (block, warnings) =
+            CodeBlock::parse(r#"rust {linenos, hl_lines=[3,5-7], title="src/\"main\".rs"}"#);
+        assert!(warnings.is_empty(), "{:?}", warnings);
+        assert_eq!(
+            block,
+            CodeBlock {
+                language: String::from("rust"),
+                line_numbers: true,
+                highlighted: vec![3..=3, 5..=7],
+                title: Some(String::from("src/\"main\".rs")),
+            }
+        );
+
+        let (block, warnings) = CodeBlock::parse("rust");
+        assert!(warnings.is_empty());
+        assert_eq!(
+            block,
+            CodeBlock {
+                language: String::from("rust"),
+                ..CodeBlock::default()
+            }
+        );
+
+        let (block, warnings) =
+            CodeBlock::parse("{linenos=maybe, hl_lines=[0], colour=red, title}");
+        assert_eq!(block, CodeBlock::default());
+        assert_eq!(warnings.len(), 4, "{:?}", warnings);
+        assert!(warnings[2].contains("unknown code block attribute 'colour'"));
+    }
+
+    #[test]
+    fn renders_lines_and_titles() {
+        let (block, _) = CodeBlock::parse("js {linenos, hl_lines=[2], title=\"a<b>.js\"}");
+        let code = "<span class=\"comment\">/* one\ntwo */</span>\nlet x;\n";
+        assert_eq!(
+            block.render("<pre><code>", code),
+            "<figure class=\"code-block\"><figcaption>a&lt;b&gt;.js</figcaption><pre><code>\
+             <span class=\"line\"><span class=\"line-number\" aria-hidden=\"true\">1</span><span class=\"comment\">/* one</span></span>\n\
+             <span class=\"line highlighted\"><span class=\"line-number\" aria-hidden=\"true\">2</span><span class=\"comment\">two */</span></span>\n\
+             <span class=\"line\"><span class=\"line-number\" aria-hidden=\"true\">3</span>let x;</span>\n\
+             </code></pre></figure>"
+        );
+    }
+
+    #[test]
+    fn renders_through_markdown() {
+        let syntax_set = syntect::parsing::SyntaxSet::load_defaults_newlines();
+        let code_blocks = crate::config::CodeBlocks::default();
+        let render = |src: &str| super::super::render(src, &syntax_set, &code_blocks);
+
+        let plain = render("```rust\nfn main() {}\n```\n");
+        assert!(plain.warnings.is_empty());
+        assert!(String::from(plain).starts_with(
+            "<pre data-lang=\"Rust\"><code class=\"language-rust\"><span class=\"source rust\">"
+        ));
+
+        let attributed = render("```nope {hl_lines=[1], frobnicate}\n<b>\n```\n");
+        assert_eq!(
+            attributed.warnings,
+            vec![String::from("unknown code block attribute 'frobnicate'")]
+        );
+        assert_eq!(
+            String::from(attributed),
+            "<pre data-lang=\"nope\"><code class=\"language-nope\">\
+             <span class=\"line highlighted\">&lt;b&gt;</span>\n</code></pre>\n"
+        );
+    }
+}
@@ -1,112 +1,91 @@
+mod code;
+
 use pulldown_cmark::{html, CodeBlockKind, Event, Options, Parser, Tag, TagEnd};
 use syntect::html::{ClassStyle, ClassedHTMLGenerator};
-use syntect::parsing::SyntaxSet;
+use syntect::parsing::{SyntaxReference, SyntaxSet};
+use syntect::util::LinesWithEndings;
 
-enum CodeHighlightingState<'a> {
-    NotInCodeBlock,
-    RequiresFirstLineParse,
-    UnknownSyntax,
-    KnownSyntax(ClassedHTMLGenerator<'a>),
-}
+use crate::config::CodeBlocks;
+use code::CodeBlock;
 
 /// The result of rendering the content with Markdown.
-pub struct Rendered(String);
+pub struct Rendered {
+    html: String,
+    /// Problems which did not stop the content from rendering, e.g. unknown
+    /// code block attributes.
+    pub(crate) warnings: Vec<String>,
+}
 
 impl From<Rendered> for String {
     fn from(value: Rendered) -> Self {
-        value.0
+        value.html
     }
 }
 
-pub(super) fn render<S: AsRef<str>>(src: S, syntax_set: &SyntaxSet) -> Result<Rendered, String> {
+/// A code block whose contents are still being collected.
+struct Pending<'a> {
+    kind: CodeBlockKind<'a>,
+    block: CodeBlock,
+    code: String,
+}
+
+pub(super) fn render<S: AsRef<str>>(
+    src: S,
+    syntax_set: &SyntaxSet,
+    code_blocks: &CodeBlocks,
+) -> Rendered {
     let src = src.as_ref();
     let mut options = Options::all();
     options.set(Options::ENABLE_OLD_FOOTNOTES, false);
     options.set(Options::ENABLE_FOOTNOTES, true);
     let parser = Parser::new_ext(src, options);
-    let mut state = CodeHighlightingState::NotInCodeBlock;
+
+    // Code blocks are collected and rendered all at once at their ends, since
+    // indented blocks can only tell their language from their first line, and
+    // line numbers and highlighted lines need the whole block.
+    let mut pending: Option<Pending> = None;
+    let mut warnings = Vec::new();
 
     let mut events = Vec::<Event>::with_capacity(src.len() * 2);
     for event in parser {
         match event {
-            Event::Text(text) => match &mut state {
-                // This is a little quirky: it hands off the text to the highlighter
-                // and relies on correctly calling `highlighter.finalize()` when we
-                // reach the end of the code block.
-                CodeHighlightingState::KnownSyntax(ref mut generator) => {
-                    generator.parse_html_for_line_which_includes_newline(text.as_ref());
-                    events.push(Event::Text("".into()));
+            Event::Start(Tag::CodeBlock(kind)) => {
+                if pending.is_some() {
+                    unreachable!("should never be entering a codeblock when already in a codeblock")
                 }
-                // This has the same constraint as `KnownSyntax`, but requires that
-                // we also try to get a
-                CodeHighlightingState::RequiresFirstLineParse => {
-                    match syntax_set.find_syntax_by_first_line(&text) {
-                        Some(definition) => {
-                            let mut generator = ClassedHTMLGenerator::new_with_class_style(
-                                definition,
-                                syntax_set,
-                                ClassStyle::Spaced,
-                            );
-                            events.push(Event::Html(
-                                format!(
-                                    "<pre lang='{name}'><code class='{name}'>",
-                                    name = definition.name
-                                )
-                                .into(),
-                            ));
-                            generator.parse_html_for_line_which_includes_newline(&text);
-                            state = CodeHighlightingState::KnownSyntax(generator);
-                            events.push(Event::Text("".into()));
-                        }
-                        None => {
-                            events.push(Event::Html("<pre><code>".to_string().into()));
-                            state = CodeHighlightingState::UnknownSyntax;
-                            events.push(Event::Text(text));
-                        }
+
+                let block = match &kind {
+                    CodeBlockKind::Fenced(info) => {
+                        let (block, block_warnings) = CodeBlock::parse(info);
+                        warnings.extend(block_warnings);
+                        block
                     }
-                }
-                CodeHighlightingState::UnknownSyntax | CodeHighlightingState::NotInCodeBlock => {
-                    events.push(Event::Text(text))
-                }
-            },
-            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(name))) => {
-                if let Some(looked_up) = syntax_set.find_syntax_by_token(name.as_ref()) {
-                    state = CodeHighlightingState::KnownSyntax(
-                        ClassedHTMLGenerator::new_with_class_style(
-                            looked_up,
-                            syntax_set,
-                            ClassStyle::Spaced,
-                        ),
-                    );
-                    let html = format!("<pre><code class='{}'>", looked_up.name);
-                    events.push(Event::Html(html.into()));
-                } else {
-                    state = CodeHighlightingState::UnknownSyntax;
-                    events.push(Event::Html("<pre><code>".into()));
-                }
+                    CodeBlockKind::Indented => CodeBlock::default(),
+                };
+                pending = Some(Pending {
+                    kind,
+                    block,
+                    code: String::new(),
+                });
             }
-            Event::Start(Tag::CodeBlock(CodeBlockKind::Indented)) => match state {
-                CodeHighlightingState::NotInCodeBlock => {
-                    state = CodeHighlightingState::RequiresFirstLineParse;
-                }
-                _ => {
-                    unreachable!("should never be entering a codeblock when already in a codeblock")
-                }
+            Event::Text(text) => match &mut pending {
+                Some(Pending { code, .. }) => code.push_str(&text),
+                None => events.push(Event::Text(text)),
             },
-            Event::End(TagEnd::CodeBlock) => match state {
-                CodeHighlightingState::KnownSyntax(generator) => {
-                    let highlighted = generator.finalize();
-                    state = CodeHighlightingState::NotInCodeBlock;
-                    events.push(Event::Html((highlighted + "</code></pre>").into()));
-                }
-                CodeHighlightingState::UnknownSyntax
-                | CodeHighlightingState::RequiresFirstLineParse => {
-                    state = CodeHighlightingState::NotInCodeBlock;
-                    events.push(Event::Html("</code></pre>".into()));
-                }
-                CodeHighlightingState::NotInCodeBlock => {
-                    unreachable!("Cannot *not* be in a code block when ending a code block")
+            Event::End(TagEnd::CodeBlock) => match pending.take() {
+                Some(Pending { kind, block, code }) => {
+                    let syntax = match kind {
+                        CodeBlockKind::Fenced(_) => {
+                            syntax_set.find_syntax_by_token(&block.language)
+                        }
+                        CodeBlockKind::Indented => LinesWithEndings::from(&code)
+                            .next()
+                            .and_then(|line| syntax_set.find_syntax_by_first_line(line)),
+                    };
+                    let html = render_code_block(&block, &code, syntax, syntax_set, code_blocks);
+                    events.push(Event::Html(format!("{}\n", html).into()));
                 }
+                None => unreachable!("Cannot *not* be in a code block when ending a code block"),
             },
             _ => events.push(event),
         }
@@ -116,5 +95,150 @@ pub(super) fn render<S: AsRef<str>>(src: S, syntax_set: &SyntaxSet) -> Result<Re
 
     html::push_html(&mut html_output, events.into_iter());
 
-    Ok(Rendered(html_output))
+    Rendered {
+        html: html_output,
+        warnings,
+    }
+}
+
+/// Render a code block, highlighted if its language is known. Every code block
+/// gets the same markup, whether fenced or indented:
+///
+/// ```html
+/// <pre data-lang="Rust"><code class="language-rust">...</code></pre>
+/// ```
+///
+/// The class is the language's name in lowercase, with anything other than
+/// letters, numbers, `+`, and `#` replaced by `-`, after the configured prefix.
+/// A fenced block whose language is unknown still gets both, with the language
+/// as written; a block with no language at all gets neither.
+fn render_code_block(
+    block: &CodeBlock,
+    code: &str,
+    syntax: Option<&SyntaxReference>,
+    syntax_set: &SyntaxSet,
+    code_blocks: &CodeBlocks,
+) -> String {
+    let language = match syntax {
+        Some(syntax) => Some(syntax.name.as_str()),
+        None if !block.language.is_empty() => Some(block.language.as_str()),
+        None => None,
+    };
+
+    let open = match language {
+        Some(language) => {
+            let data_lang = if code_blocks.data_lang {
+                format!(" data-lang=\"{}\"", code::escape(language))
+            } else {
+                String::new()
+            };
+            format!(
+                "<pre{}><code class=\"{}\">",
+                data_lang,
+                code::escape(&format!(
+                    "{}{}",
+                    code_blocks.class_prefix,
+                    class_name(language)
+                ))
+            )
+        }
+        None => String::from("<pre><code>"),
+    };
+
+    let highlighted = match syntax {
+        Some(syntax) => {
+            let mut generator =
+                ClassedHTMLGenerator::new_with_class_style(syntax, syntax_set, ClassStyle::Spaced);
+            for line in LinesWithEndings::from(code) {
+                generator.parse_html_for_line_which_includes_newline(line);
+            }
+            generator.finalize()
+        }
+        None => code::escape(code),
+    };
+
+    block.render(&open, &highlighted)
+}
+
+/// The name of a language as (part of) a class name, e.g. `graphviz-dot` for
+/// `Graphviz (DOT)`.
+fn class_name(language: &str) -> String {
+    language
+        .to_lowercase()
+        .split(|c: char| !(c.is_alphanumeric() || c == '+' || c == '#'))
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The directory of snapshot cases: each `<name>.md` is rendered and
+    /// compared to `<name>.html`. Run the tests with `LX_UPDATE_SNAPSHOTS` set
+    /// to write the `.html` files from the current output instead, then review
+    /// the changes.
+    const SNAPSHOTS: &str = "src/markdown/snapshots";
+
+    #[test]
+    fn snapshots() {
+        let syntax_set = SyntaxSet::load_defaults_newlines();
+        let code_blocks = CodeBlocks::default();
+        let update = std::env::var_os("LX_UPDATE_SNAPSHOTS").is_some();
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join(SNAPSHOTS);
+
+        let mut cases: Vec<_> = std::fs::read_dir(&dir)
+            .expect("has snapshots")
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|path| path.extension() == Some("md".as_ref()))
+            .collect();
+        cases.sort();
+        assert!(!cases.is_empty());
+
+        let mut mismatched = Vec::new();
+        for case in cases {
+            let src = std::fs::read_to_string(&case).unwrap();
+            let rendered = render(&src, &syntax_set, &code_blocks);
+            let mut actual = String::new();
+            for warning in &rendered.warnings {
+                actual.push_str(&format!("<!-- warning: {} -->\n", warning));
+            }
+            actual.push_str(&String::from(rendered));
+
+            let snapshot = case.with_extension("html");
+            if update {
+                std::fs::write(&snapshot, &actual).unwrap();
+            } else if std::fs::read_to_string(&snapshot).ok().as_ref() != Some(&actual) {
+                mismatched.push(format!("{}:\n{}", snapshot.display(), actual));
+            }
+        }
+
+        assert!(
+            mismatched.is_empty(),
+            "rendered differently from the snapshots (set LX_UPDATE_SNAPSHOTS to update):\n\n{}",
+            mismatched.join("\n\n")
+        );
+    }
+
+    #[test]
+    fn markup_is_configurable_and_escaped() {
+        let syntax_set = SyntaxSet::load_defaults_newlines();
+        let mut code_blocks = CodeBlocks {
+            class_prefix: String::from("lang-"),
+            data_lang: false,
+        };
+
+        let rendered = render("```dot\ndigraph {}\n```\n", &syntax_set, &code_blocks);
+        assert!(String::from(rendered).starts_with("<pre><code class=\"lang-graphviz-dot\">"));
+
+        code_blocks.class_prefix = String::from("\"x ");
+        code_blocks.data_lang = true;
+        let rendered = render("```\"><script>\nhi\n```\n", &syntax_set, &code_blocks);
+        assert_eq!(
+            String::from(rendered),
+            "<pre data-lang=\"&quot;&gt;&lt;script&gt;\"><code class=\"&quot;x script\">hi\n</code></pre>\n"
+        );
+    }
 }
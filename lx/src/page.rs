@@ -9,6 +9,7 @@ use std::{
 };
 
 use serde::{Deserialize, Serialize};
+use serde_yaml::{Mapping, Value};
 use syntect::parsing::SyntaxSet;
 use uuid::Uuid;
 
@@ -16,8 +17,9 @@ use crate::markdown::{self, Rendered};
 use components::Components;
 
 use crate::config::Config;
+use crate::error::Error;
 
-use self::metadata::Metadata;
+use self::metadata::{cascade::Cascade, Metadata};
 
 /// Source data for a file: where it came from, and its original contents.
 pub struct Source {
@@ -31,6 +33,15 @@ pub struct Source {
 #[derive(Debug, Hash, PartialEq, Eq, PartialOrd, Deserialize, Serialize)]
 pub struct Id(Uuid);
 
+impl Id {
+    fn for_source(source: &Source) -> Id {
+        Id(Uuid::new_v5(
+            &Uuid::NAMESPACE_OID,
+            source.contents.as_bytes(),
+        ))
+    }
+}
+
 /// A fully-resolved representation of a page.
 ///
 /// In this struct, the metadata has been parsed and resolved, and the content
@@ -38,15 +49,24 @@ pub struct Id(Uuid);
 /// templating engine and my typography tooling. It is ready to render into the
 /// target layout template specified by its `metadata: ResolvedMetadata` and
 /// then to print to the file system.
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct Page {
     pub id: Id,
 
+    /// Where the page came from on disk.
+    #[serde(skip)]
+    pub source_path: PathBuf,
+
     /// The fully-parsed metadata associated with the page.
     pub metadata: Metadata,
 
     /// The fully-rendered contents of the page.
     pub contents: PostProcessed,
+
+    /// Warnings from rendering the contents (e.g. about code blocks), kept so
+    /// that the page reports them again when it comes from the cache.
+    #[serde(skip)]
+    pub(crate) warnings: Vec<String>,
 }
 
 impl Page {
@@ -55,23 +75,54 @@ impl Page {
         root_dir: &Path,
         syntax_set: &SyntaxSet,
         config: &Config,
-    ) -> Result<Self, String> {
-        let id = Id(Uuid::new_v5(
-            &Uuid::NAMESPACE_OID,
-            source.contents.as_bytes(),
-        ));
+        cascade: &Cascade,
+    ) -> Result<Self, Error> {
+        let id = Id::for_source(source);
 
-        let Components { header, body } = Components::try_from(source.contents.as_ref())?;
-        let metadata = Metadata::new(&source.path, root_dir, header)?;
+        let Components { header, body } = Components::try_from(source)?;
+        let metadata = Metadata::new(&source.path, root_dir, header, config, cascade)?;
 
         let preprocessed = Preprocessed::from_str(body, config, &metadata);
-        let rendered_as_html = markdown::render(preprocessed, syntax_set)?;
+        let mut rendered_as_html = markdown::render(preprocessed, syntax_set, &config.code_blocks);
+        let warnings = std::mem::take(&mut rendered_as_html.warnings);
+        warn(&source.path, &warnings);
         let contents = postprocess(rendered_as_html, config, &metadata);
 
         Ok(Page {
             id,
+            source_path: source.path.clone(),
             metadata,
             contents,
+            warnings,
+        })
+    }
+
+    /// Rebuild a page from the resolved front matter and rendered contents of
+    /// a previous build of the same source (see [`crate::cache`]), skipping the
+    /// Markdown rendering and syntax highlighting. The `warnings` rendering
+    /// produced then are reported again.
+    pub(crate) fn from_cache(
+        source: &Source,
+        root_dir: &Path,
+        config: &Config,
+        front_matter: Mapping,
+        contents: PostProcessed,
+        warnings: Vec<String>,
+    ) -> Result<Self, Error> {
+        let metadata = Metadata::from_front_matter(
+            &source.path,
+            root_dir,
+            Value::Mapping(front_matter),
+            config,
+        )?;
+
+        warn(&source.path, &warnings);
+        Ok(Page {
+            id: Id::for_source(source),
+            source_path: source.path.clone(),
+            metadata,
+            contents,
+            warnings,
         })
     }
 
@@ -79,15 +130,20 @@ impl Page {
         root_dir.join(&self.metadata.slug)
     }
 
+    /// Where the rendered page goes in the site's output directory.
+    pub fn output_path(&self, config: &Config) -> PathBuf {
+        config.output_path(&self.metadata.slug)
+    }
+
     /// Given a config, generate the (canonicalized) URL for the page
-    pub fn _url(&self, config: &Config) -> String {
-        String::from(config.url.trim_end_matches('/')) + "/" + &self.metadata.slug
+    pub fn url(&self, config: &Config) -> String {
+        config.url_for(&self.metadata.slug)
     }
 }
 
-impl From<&Page> for lx_json_feed::FeedItem {
-    fn from(_: &Page) -> Self {
-        unimplemented!()
+fn warn(path: &Path, warnings: &[String]) {
+    for warning in warnings {
+        eprintln!("warning: {}: {}", path.display(), warning);
     }
 }
 
@@ -111,7 +167,7 @@ impl<'a> AsRef<str> for Preprocessed<'a> {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct PostProcessed(String);
 
 impl AsRef<[u8]> for PostProcessed {
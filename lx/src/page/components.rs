@@ -3,6 +3,9 @@ use std::convert::TryFrom;
 use lazy_static::lazy_static;
 use regex::Regex;
 
+use crate::error::Error;
+use crate::page::Source;
+
 lazy_static! {
     static ref COMPONENTS: Regex =
         Regex::new(r"^[[:space:]]*---(?P<header>\n(?s).*?(?-s))---\n(?P<body>(?s).*(?-s))$")
@@ -14,13 +17,13 @@ pub(crate) struct Components<'c> {
     pub(crate) body: &'c str,
 }
 
-impl<'s> TryFrom<&'s str> for Components<'s> {
-    type Error = String;
+impl<'s> TryFrom<&'s Source> for Components<'s> {
+    type Error = Error;
 
-    fn try_from(source: &'s str) -> Result<Self, Self::Error> {
-        let captures = COMPONENTS
-            .captures(source)
-            .ok_or("invalid/missing YAML header")?;
+    fn try_from(source: &'s Source) -> Result<Self, Self::Error> {
+        let captures = COMPONENTS.captures(&source.contents).ok_or_else(|| {
+            Error::invalid_front_matter(&source.path, "invalid/missing YAML header")
+        })?;
 
         let header = captures
             .name("header")
@@ -1,10 +1,22 @@
+pub mod cascade;
 mod serial;
 
 use std::path::Path;
 
 use chrono::{DateTime, FixedOffset};
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use serde_derive::Serialize;
+use serde_yaml::{Mapping, Value};
 use serial::{Book, Qualifiers, Series, Subscribe};
 
+use crate::config::permalink::Fields;
+use crate::config::{Config, Permalinks};
+use crate::error::Error;
+
+use self::cascade::Cascade;
+
+pub(crate) use serial::{TermTree, Terms};
+
 #[derive(Debug)]
 pub enum RequiredFields {
     Title(String),
@@ -15,102 +27,438 @@ pub enum RequiredFields {
     },
 }
 
+/// Templates should not have to care which of the variants they got: they just
+/// see `title` and/or `date`, exactly as they were written in the header.
+impl Serialize for RequiredFields {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        match self {
+            RequiredFields::Title(title) => map.serialize_entry("title", title)?,
+            RequiredFields::Date(date) => map.serialize_entry("date", date)?,
+            RequiredFields::Both { title, date } => {
+                map.serialize_entry("title", title)?;
+                map.serialize_entry("date", date)?;
+            }
+        }
+        map.end()
+    }
+}
+
 /// Metadata after combining the header config with all items in data hierarchy,
 /// including the root config.
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct Metadata {
     /// The date, title, or both (every item must have one or the other)
+    #[serde(flatten)]
     required: RequiredFields,
 
     /// The path to this piece of content.
     pub slug: String,
 
-    layout: String,
+    /// Other paths this piece of content used to live at, each of which
+    /// redirects to it (see [`crate::redirects`]), e.g. `/notes/0634/`.
+    pub(crate) aliases: Vec<String>,
+
+    /// The top-level directory under `content` containing this piece of
+    /// content, if it is not at the root, e.g. `journal`.
+    #[serde(skip)]
+    pub(crate) section: Option<String>,
+
+    /// The name of the layout template to render this piece of content with.
+    pub layout: String,
 
     subtitle: Option<String>,
-    summary: Option<String>,
+    pub(crate) summary: Option<String>,
     qualifiers: Option<Qualifiers>,
-    updated: Option<DateTime<FixedOffset>>,
+    pub(crate) updated: Option<DateTime<FixedOffset>>,
     thanks: Option<String>,
-    tags: Vec<String>,
+    pub(crate) tags: Vec<String>,
     featured: bool,
     book: Option<Book>,
     series: Option<Series>,
     subscribe: Option<Subscribe>,
+
+    /// The full front matter after resolving the data cascade, including any
+    /// keys which have no dedicated field, e.g. for custom taxonomies.
+    #[serde(skip)]
+    pub(crate) front_matter: Mapping,
+}
+
+impl RequiredFields {
+    fn title(&self) -> Option<&str> {
+        match self {
+            RequiredFields::Title(title) | RequiredFields::Both { title, .. } => Some(title),
+            RequiredFields::Date(..) => None,
+        }
+    }
+
+    fn date(&self) -> Option<&DateTime<FixedOffset>> {
+        match self {
+            RequiredFields::Date(date) | RequiredFields::Both { date, .. } => Some(date),
+            RequiredFields::Title(..) => None,
+        }
+    }
 }
 
 impl Metadata {
-    pub(super) fn new(src_path: &Path, root_dir: &Path, header: &str) -> Result<Metadata, String> {
+    pub fn title(&self) -> Option<&str> {
+        self.required.title()
+    }
+
+    pub fn date(&self) -> Option<&DateTime<FixedOffset>> {
+        self.required.date()
+    }
+
+    /// Check that `header` (from the file at `path`) is valid front matter for
+    /// an item by itself, i.e. without anything the data cascade might supply.
+    pub(crate) fn validate(path: &Path, header: &str) -> Result<(), Error> {
         let item_metadata: serial::Metadata =
-            serde_yaml::from_str(header).map_err(|e| format!("{}", e))?;
-
-        let required = (match (item_metadata.title, item_metadata.date) {
-            (Some(title), Some(date)) => Ok(RequiredFields::Both { title, date }),
-            (None, Some(date)) => Ok(RequiredFields::Date(date)),
-            (Some(title), None) => Ok(RequiredFields::Title(title)),
-            (None, None) => Err(String::from("missing date and title")),
-        })?;
-
-        let slug = item_metadata
-            .permalink
-            .map(|permalink| {
-                permalink
-                    .trim_start_matches('/')
-                    .trim_end_matches('/')
-                    .to_string()
-            })
-            .unwrap_or_else(|| {
-                let slug = slug::slugify(
-                    src_path
-                        .file_stem()
-                        .and_then(|stem| stem.to_str())
-                        .unwrap_or_else(|| {
-                            panic!(
-                                "it should be impossible to get here without a valid source path"
-                            )
-                        }),
-                );
-
-                src_path
-                    .strip_prefix(root_dir)
-                    .and_then(|local_path| {
-                        local_path
-                            .parent()
-                            .map(|containing_dir| containing_dir.join(&slug))
-                            .ok_or_else(|| {
-                                panic!(
-                                    "could not construct containing dir in {}",
-                                    local_path.display()
-                                )
-                            })
-                    })
-                    .unwrap_or_else(|e| {
-                        panic!(
-                            "error constructing a valid *merged* source path given {}, {}: {}",
-                            src_path.display(),
-                            root_dir.display(),
-                            e
-                        )
-                    })
-                    .to_string_lossy()
-                    .to_owned()
-                    .to_string()
-            });
+            serde_yaml::from_str(header).map_err(|e| Error::front_matter(path, e))?;
+        match (item_metadata.title, item_metadata.date) {
+            (None, None) => Err(missing_title_and_date(path)),
+            _ => Ok(()),
+        }
+    }
+
+    pub(super) fn new(
+        src_path: &Path,
+        root_dir: &Path,
+        header: &str,
+        config: &Config,
+        cascade: &Cascade,
+    ) -> Result<Metadata, Error> {
+        let parsed = serde_yaml::from_str(header).map_err(|e| Error::front_matter(src_path, e))?;
+        let resolved = cascade.resolve(src_path, root_dir, parsed);
+        Metadata::from_front_matter(src_path, root_dir, resolved, config).map_err(|e| {
+            // A mistake in the item's own header is worth pointing to where it
+            // is in the header, rather than somewhere in the merged data.
+            match serde_yaml::from_str::<serial::Metadata>(header) {
+                Err(header_error) => Error::front_matter(src_path, header_error),
+                Ok(_) => e,
+            }
+        })
+    }
+
+    /// Build the metadata from front matter which has already been resolved
+    /// against the data cascade, e.g. by a previous build.
+    pub(crate) fn from_front_matter(
+        src_path: &Path,
+        root_dir: &Path,
+        resolved: Value,
+        config: &Config,
+    ) -> Result<Metadata, Error> {
+        let front_matter = match &resolved {
+            Value::Mapping(mapping) => mapping.clone(),
+            _ => Mapping::new(),
+        };
+
+        // Round-trip through the YAML text rather than deserializing straight
+        // from the `Value`: a `Value` has already decided that `year: 2015` is a
+        // number, whereas deserializing from text lets it be a `String`.
+        let resolved = serde_yaml::to_string(&resolved)
+            .map_err(|e| Error::resolved_front_matter(src_path, e))?;
+        let item_metadata: serial::Metadata = serde_yaml::from_str(&resolved)
+            .map_err(|e| Error::resolved_front_matter(src_path, e))?;
+
+        let required = match (item_metadata.title, item_metadata.date) {
+            (Some(title), Some(date)) => RequiredFields::Both { title, date },
+            (None, Some(date)) => RequiredFields::Date(date),
+            (Some(title), None) => RequiredFields::Title(title),
+            (None, None) => return Err(missing_title_and_date(src_path)),
+        };
+
+        let slug = match item_metadata.permalink {
+            Some(permalink) => permalink
+                .trim_start_matches('/')
+                .trim_end_matches('/')
+                .to_string(),
+            None => slug_from_path(
+                src_path,
+                root_dir,
+                &config.permalinks,
+                &required,
+                item_metadata
+                    .series
+                    .as_ref()
+                    .and_then(|series| series.name.as_deref()),
+            )?,
+        };
+
+        let aliases = item_metadata
+            .aliases
+            .iter()
+            .map(|alias| alias_path(src_path, alias))
+            .collect::<Result<_, _>>()?;
+
+        let section = src_path
+            .strip_prefix(root_dir)
+            .ok()
+            .and_then(|local_path| local_path.parent())
+            .and_then(|dir| dir.iter().next())
+            .map(|section| section.to_string_lossy().into_owned());
 
         Ok(Metadata {
             required,
             slug,
+            aliases,
+            section,
             subtitle: item_metadata.subtitle,
-            layout: String::from("base.html"), // TODO: not this!
+            layout: item_metadata
+                .layout
+                .unwrap_or_else(|| config.layouts.default.clone()),
             summary: item_metadata.summary,
             qualifiers: item_metadata.qualifiers,
             updated: item_metadata.updated,
             thanks: item_metadata.thanks,
-            tags: item_metadata.tags,
+            tags: item_metadata.tags.names(),
             featured: item_metadata.featured,
             book: item_metadata.book,
             series: item_metadata.series,
             subscribe: item_metadata.subscribe,
+            front_matter,
         })
     }
 }
+
+fn missing_title_and_date(path: &Path) -> Error {
+    Error::MissingField {
+        path: path.to_owned(),
+        field: String::from("a title or a date (or both)"),
+    }
+}
+
+/// An alias as a path from the root of the site, e.g. `/notes/0634/` for
+/// `notes/0634/`. Aliases are single paths, so they cannot use the wildcards
+/// of the redirects file.
+fn alias_path(path: &Path, alias: &str) -> Result<String, Error> {
+    let invalid = |reason: &str| {
+        Err(Error::invalid_front_matter(
+            path,
+            format!("invalid alias '{}': {}", alias, reason),
+        ))
+    };
+
+    if alias.trim_matches('/').is_empty() {
+        return invalid("it is empty");
+    }
+    if alias.contains(char::is_whitespace) {
+        return invalid("it contains whitespace");
+    }
+    if alias.contains(&['*', '?', '#'][..])
+        || alias.split('/').any(|segment| segment.starts_with(':'))
+    {
+        return invalid(
+            "it must be a single path, without wildcards, placeholders, queries, or fragments",
+        );
+    }
+    if alias.contains("://") {
+        return invalid("it must be a path on this site, not a URL");
+    }
+
+    Ok(format!("/{}", alias.trim_start_matches('/')))
+}
+
+/// The slug for an item without a `permalink`: from the permalink pattern for
+/// its directory if there is one, or else its path relative to the content
+/// directory with its file name slugified, e.g. `journal/hello-world` for
+/// `content/journal/Hello, World!.md`.
+fn slug_from_path(
+    src_path: &Path,
+    root_dir: &Path,
+    permalinks: &Permalinks,
+    required: &RequiredFields,
+    series: Option<&str>,
+) -> Result<String, Error> {
+    let error = |message: String| Error::Slug {
+        path: src_path.to_owned(),
+        message,
+    };
+
+    let local_path = src_path.strip_prefix(root_dir).map_err(|_| {
+        error(format!(
+            "it is not in the content directory '{}'",
+            root_dir.display()
+        ))
+    })?;
+    let containing_dir = local_path
+        .parent()
+        .ok_or_else(|| error(String::from("it has no containing directory")))?
+        .to_str()
+        .ok_or_else(|| error(String::from("its directory is not valid UTF-8")))?;
+    let stem = local_path
+        .file_stem()
+        .ok_or_else(|| error(String::from("it has no file name")))?
+        .to_str()
+        .ok_or_else(|| error(String::from("its file name is not valid UTF-8")))?;
+
+    let slug = slug::slugify(stem);
+    if let Some(pattern) = permalinks.pattern_for(containing_dir) {
+        return pattern
+            .expand(&Fields {
+                slug: Some(slug.as_str()).filter(|slug| !slug.is_empty()),
+                path: containing_dir,
+                title: required.title(),
+                series,
+                date: required.date(),
+            })
+            .map_err(error);
+    }
+
+    if slug.is_empty() {
+        return Err(error(format!(
+            "its file name ('{}') has nothing to make a slug from",
+            stem
+        )));
+    }
+
+    Ok(Path::new(containing_dir)
+        .join(slug)
+        .to_string_lossy()
+        .into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROOT_DIR: &str = "/site/content";
+
+    fn slug(path: &Path, permalinks: &Permalinks) -> Result<String, Error> {
+        let required = RequiredFields::Both {
+            title: String::from("Hello, World!"),
+            date: DateTime::parse_from_rfc3339("2020-03-04T06:34:00-07:00").unwrap(),
+        };
+        slug_from_path(
+            path,
+            Path::new(ROOT_DIR),
+            permalinks,
+            &required,
+            Some("Reading Notes"),
+        )
+    }
+
+    fn slug_for(path: &str) -> Result<String, Error> {
+        slug(&Path::new(ROOT_DIR).join(path), &Permalinks::default())
+    }
+
+    #[test]
+    fn slugs_come_from_the_path() {
+        assert_eq!(
+            slug_for("journal/Hello, World!.md").unwrap(),
+            "journal/hello-world"
+        );
+        assert_eq!(slug_for("Über Straße.md").unwrap(), "uber-strasse");
+        assert_eq!(slug_for("notes/2020/03.04.md").unwrap(), "notes/2020/03-04");
+    }
+
+    #[test]
+    fn slugs_come_from_the_nearest_permalink_pattern() {
+        let permalinks: Permalinks = json5::from_str(
+            r#"{
+                sections: {
+                    "": "/{path}/{title}/",
+                    journal: "/journal/{year}/{month}/{slug}/",
+                    "journal/series": "/{series}/{slug}",
+                    notes: "/notes/{date:%Y-%m-%d-%H%M}/",
+                },
+            }"#,
+        )
+        .expect("valid permalinks");
+        let slug_for = |path: &str| slug(&Path::new(ROOT_DIR).join(path), &permalinks);
+
+        assert_eq!(slug_for("about.md").unwrap(), "hello-world");
+        assert_eq!(slug_for("essays/post.md").unwrap(), "essays/hello-world");
+        assert_eq!(
+            slug_for("journal/entry.md").unwrap(),
+            "journal/2020/03/entry"
+        );
+        assert_eq!(
+            slug_for("journal/2020/deep.md").unwrap(),
+            "journal/2020/03/deep"
+        );
+        assert_eq!(
+            slug_for("journal/series/part-1.md").unwrap(),
+            "reading-notes/part-1"
+        );
+        assert_eq!(slug_for("notes/!!!.md").unwrap(), "notes/2020-03-04-0634");
+        assert!(matches!(
+            slug_for("journal/!!!.md"),
+            Err(Error::Slug { .. })
+        ));
+    }
+
+    #[test]
+    fn pathological_paths_are_errors() {
+        let is_slug_error =
+            |result: Result<String, Error>| matches!(result, Err(Error::Slug { .. }));
+        let permalinks = Permalinks::default();
+
+        assert!(is_slug_error(slug_for("!!!.md")));
+        assert!(is_slug_error(slug_for("  .md")));
+        assert!(is_slug_error(slug(
+            Path::new("/elsewhere/a.md"),
+            &permalinks
+        )));
+        assert!(is_slug_error(slug(Path::new(ROOT_DIR), &permalinks)));
+
+        #[cfg(unix)]
+        {
+            use std::ffi::OsStr;
+            use std::os::unix::ffi::OsStrExt;
+
+            let root_dir = Path::new(ROOT_DIR);
+            let not_utf8 = root_dir.join(OsStr::from_bytes(b"caf\xe9.md"));
+            assert!(is_slug_error(slug(&not_utf8, &permalinks)));
+            let not_utf8_dir = root_dir.join(OsStr::from_bytes(b"caf\xe9")).join("a.md");
+            assert!(is_slug_error(slug(&not_utf8_dir, &permalinks)));
+        }
+    }
+
+    #[test]
+    fn aliases_are_single_paths() {
+        let path = Path::new("/site/content/notes/2020-04-29-0634.md");
+        assert_eq!(alias_path(path, "notes/0634/").unwrap(), "/notes/0634/");
+        assert_eq!(alias_path(path, "/notes/06:34").unwrap(), "/notes/06:34");
+
+        for alias in &[
+            "/",
+            "/notes/06 34/",
+            "/notes/*",
+            "/notes/:id",
+            "/notes?id=0634",
+            "https://example.com/notes/0634/",
+        ] {
+            assert!(alias_path(path, alias).is_err(), "{}", alias);
+        }
+    }
+
+    #[test]
+    fn front_matter_errors_point_into_the_header_or_at_the_cascade() {
+        let config = crate::test_site::config("");
+        let root_dir = Path::new(ROOT_DIR);
+        let path = root_dir.join("journal/entry.md");
+        let book: Mapping = serde_yaml::from_str("book: A Title").unwrap();
+        let cascade = Cascade::new(
+            vec![(root_dir.join("journal"), book)].into_iter().collect(),
+            Default::default(),
+        );
+
+        let header = "title: Entry\nsummary: Fine\nfeatured: maybe\n";
+        match Metadata::new(&path, root_dir, header, &config, &cascade) {
+            Err(Error::FrontMatter {
+                location: Some(location),
+                ..
+            }) => assert_eq!(location.line, 3),
+            other => panic!("expected a located error, got {:?}", other.map(|_| ())),
+        }
+
+        match Metadata::new(&path, root_dir, "title: Entry\n", &config, &cascade) {
+            Err(Error::FrontMatter {
+                location: None,
+                message,
+                ..
+            }) => assert!(message.contains("data cascade"), "{}", message),
+            other => panic!("expected a cascade error, got {:?}", other.map(|_| ())),
+        }
+    }
+}
@@ -0,0 +1,285 @@
+//! The data cascade: every `config.lx.yaml` in the content tree supplies
+//! metadata for all the content beneath it. The nearest directory's data wins
+//! over data from further up the tree, and the item's own header wins over all
+//! of it.
+//!
+//! Mappings are merged key-by-key all the way down, so a directory can supply
+//! `series.name` while each item supplies its own `series.part`. Lists are
+//! replaced by default; see [`ListMerge`] for the alternative.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use serde_yaml::{Mapping, Value};
+
+use crate::config::{CascadeRules, ListMerge};
+use crate::error::Error;
+
+/// Metadata which identifies a single item, and so may only come from the
+/// item's own header: every item beneath a directory which supplied it would
+/// otherwise share the same URL, title, or date.
+const ITEM_ONLY_KEYS: &[&str] = &["permalink", "aliases", "title", "slug", "date"];
+
+/// All the directory-level data for a site, keyed by the directory it applies
+/// to.
+#[derive(Debug, Default)]
+pub struct Cascade {
+    data: HashMap<PathBuf, Mapping>,
+    rules: CascadeRules,
+}
+
+impl Cascade {
+    /// Load every `config.lx.yaml` file in `paths`. An empty file is fine (and
+    /// contributes nothing); anything other than a mapping at its root is not,
+    /// and neither is any of the metadata which belongs to a single item.
+    pub fn load(paths: &[PathBuf], rules: &CascadeRules) -> Result<Cascade, Error> {
+        let mut data = HashMap::with_capacity(paths.len());
+        for path in paths {
+            let contents = std::fs::read_to_string(path).map_err(|e| Error::io(path, e))?;
+            let mapping =
+                match serde_yaml::from_str(&contents).map_err(|e| Error::cascade(path, e))? {
+                    Value::Null => Mapping::new(),
+                    Value::Mapping(mapping) => mapping,
+                    _ => {
+                        return Err(Error::Config {
+                            path: path.clone(),
+                            location: None,
+                            message: String::from("must contain a mapping of metadata"),
+                        })
+                    }
+                };
+
+            if let Some(key) = ITEM_ONLY_KEYS
+                .iter()
+                .find(|key| mapping.contains_key(**key))
+            {
+                return Err(Error::Config {
+                    path: path.clone(),
+                    location: None,
+                    message: format!(
+                        "cannot set `{}`, which only an item's own header may set",
+                        key
+                    ),
+                });
+            }
+
+            let dir = path.parent().ok_or_else(|| Error::Config {
+                path: path.clone(),
+                location: None,
+                message: String::from("has no containing dir"),
+            })?;
+            data.insert(dir.to_owned(), mapping);
+        }
+
+        Ok(Cascade::new(data, rules.clone()))
+    }
+
+    pub(crate) fn new(data: HashMap<PathBuf, Mapping>, rules: CascadeRules) -> Cascade {
+        Cascade { data, rules }
+    }
+
+    /// Merge the data for every directory from `root_dir` down to the one
+    /// containing `src_path`, and then the item's own `header` on top of that.
+    pub(crate) fn resolve(&self, src_path: &Path, root_dir: &Path, header: Value) -> Value {
+        let mut dirs = src_path
+            .ancestors()
+            .skip(1)
+            .take_while(|dir| dir.starts_with(root_dir))
+            .collect::<Vec<_>>();
+        dirs.reverse();
+
+        let merged = dirs
+            .into_iter()
+            .filter_map(|dir| self.data.get(dir))
+            .fold(Value::Mapping(Mapping::new()), |merged, data| {
+                self.merge(merged, Value::Mapping(data.clone()), "")
+            });
+
+        self.merge(merged, header, "")
+    }
+
+    /// Merge `overlay` onto `base`. `path` is the dotted path to the values
+    /// being merged, e.g. `book.editors`, used to look up list merge rules.
+    fn merge(&self, base: Value, overlay: Value, path: &str) -> Value {
+        match (base, overlay) {
+            // An explicitly-empty value does not erase what the cascade supplied.
+            (base, Value::Null) => base,
+
+            (Value::Mapping(mut base), Value::Mapping(overlay)) => {
+                for (key, value) in overlay {
+                    let child_path = match (&key, path) {
+                        (Value::String(key), "") => key.clone(),
+                        (Value::String(key), path) => format!("{}.{}", path, key),
+                        _ => String::from(path),
+                    };
+
+                    let merged = match base.remove(&key) {
+                        Some(existing) => self.merge(existing, value, &child_path),
+                        None => value,
+                    };
+                    base.insert(key, merged);
+                }
+                Value::Mapping(base)
+            }
+
+            (Value::Sequence(mut base), Value::Sequence(overlay)) => {
+                match self.rules.lists.get(path).copied().unwrap_or_default() {
+                    ListMerge::Replace => Value::Sequence(overlay),
+                    ListMerge::Append => {
+                        for item in overlay {
+                            if !base.contains(&item) {
+                                base.push(item);
+                            }
+                        }
+                        Value::Sequence(base)
+                    }
+                }
+            }
+
+            (_, overlay) => overlay,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_site::TestSite;
+
+    fn yaml(src: &str) -> Value {
+        serde_yaml::from_str(src).expect("test YAML is valid")
+    }
+
+    fn mapping(src: &str) -> Mapping {
+        match yaml(src) {
+            Value::Mapping(mapping) => mapping,
+            _ => panic!("test YAML must be a mapping"),
+        }
+    }
+
+    fn cascade(lists: &[(&str, ListMerge)]) -> Cascade {
+        let mut data = HashMap::new();
+        data.insert(
+            PathBuf::from("/site/content/library"),
+            mapping("tags: [books]\nlayout: library.html\nseries:\n  name: Outer"),
+        );
+        data.insert(
+            PathBuf::from("/site/content/library/Confronted by Grace"),
+            mapping(
+                "tags: [theology]\nbook:\n  title: Confronted by Grace\nseries:\n  name: Inner",
+            ),
+        );
+        // Outside the root: must never apply.
+        data.insert(PathBuf::from("/site"), mapping("featured: true"));
+
+        let rules = CascadeRules {
+            lists: lists
+                .iter()
+                .map(|(key, rule)| (String::from(*key), *rule))
+                .collect(),
+        };
+
+        Cascade::new(data, rules)
+    }
+
+    fn resolve(cascade: &Cascade, header: &str) -> Value {
+        cascade.resolve(
+            Path::new("/site/content/library/Confronted by Grace/God teaches us.md"),
+            Path::new("/site/content"),
+            yaml(header),
+        )
+    }
+
+    #[test]
+    fn nearest_directory_wins() {
+        let resolved = resolve(&cascade(&[]), "series:\n  part: 1");
+        assert_eq!(resolved["series"], yaml("name: Inner\npart: 1"));
+        assert_eq!(resolved["layout"], yaml("library.html"));
+        assert_eq!(resolved["book"]["title"], yaml("Confronted by Grace"));
+    }
+
+    #[test]
+    fn header_wins_over_all() {
+        let resolved = resolve(
+            &cascade(&[]),
+            "layout: post.html\nseries:\n  name: Mine\n  part: 2",
+        );
+        assert_eq!(resolved["layout"], yaml("post.html"));
+        assert_eq!(resolved["series"], yaml("name: Mine\npart: 2"));
+    }
+
+    #[test]
+    fn ignores_data_outside_root() {
+        let resolved = resolve(&cascade(&[]), "title: Hello");
+        assert_eq!(resolved.get("featured"), None);
+    }
+
+    #[test]
+    fn null_does_not_erase() {
+        let resolved = resolve(&cascade(&[]), "layout: ~");
+        assert_eq!(resolved["layout"], yaml("library.html"));
+    }
+
+    #[test]
+    fn lists_replace_by_default() {
+        let resolved = resolve(&cascade(&[]), "tags: [preaching]");
+        assert_eq!(resolved["tags"], yaml("[preaching]"));
+
+        let resolved = resolve(&cascade(&[]), "title: No tags");
+        assert_eq!(resolved["tags"], yaml("[theology]"));
+    }
+
+    #[test]
+    fn lists_can_append() {
+        let cascade = cascade(&[("tags", ListMerge::Append)]);
+        let resolved = resolve(&cascade, "tags: [preaching, books]");
+        assert_eq!(resolved["tags"], yaml("[books, theology, preaching]"));
+    }
+
+    #[test]
+    fn rejects_metadata_for_a_single_item() {
+        let site = TestSite::empty("cascade-item-only");
+        site.write(&[
+            ("content/config.lx.yaml", "layout: base.jinja"),
+            (
+                "content/journal/config.lx.yaml",
+                "tags: [journal]\ndate: 2021-06-01",
+            ),
+        ]);
+        let paths = vec![
+            site.root.join("content/config.lx.yaml"),
+            site.root.join("content/journal/config.lx.yaml"),
+        ];
+
+        match Cascade::load(&paths, &CascadeRules::default()) {
+            Err(Error::Config { path, message, .. }) => {
+                assert_eq!(path, paths[1]);
+                assert!(message.contains("`date`"), "{}", message);
+            }
+            other => panic!("expected a config error, got {:?}", other),
+        }
+        assert!(Cascade::load(&paths[..1], &CascadeRules::default()).is_ok());
+    }
+
+    #[test]
+    fn nested_list_rules_use_dotted_paths() {
+        let mut data = HashMap::new();
+        data.insert(
+            PathBuf::from("/site/content"),
+            mapping("book:\n  editors: [Alice]"),
+        );
+        let rules = CascadeRules {
+            lists: vec![(String::from("book.editors"), ListMerge::Append)]
+                .into_iter()
+                .collect(),
+        };
+        let cascade = Cascade::new(data, rules);
+
+        let resolved = cascade.resolve(
+            Path::new("/site/content/item.md"),
+            Path::new("/site/content"),
+            yaml("book:\n  editors: [Bob]"),
+        );
+        assert_eq!(resolved["book"]["editors"], yaml("[Alice, Bob]"));
+    }
+}
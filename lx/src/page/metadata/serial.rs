@@ -3,7 +3,9 @@
 //! support in data files.
 
 use chrono::{DateTime, FixedOffset};
-use serde_derive::Deserialize;
+use serde::de::{Deserialize, Deserializer, Error};
+use serde_derive::{Deserialize, Serialize};
+use serde_yaml::Value;
 
 #[derive(Deserialize, Debug)]
 pub(super) struct Metadata {
@@ -14,9 +16,11 @@ pub(super) struct Metadata {
     pub(super) date: Option<DateTime<FixedOffset>>,
     pub(super) updated: Option<DateTime<FixedOffset>>,
     pub(super) permalink: Option<String>,
+    #[serde(default)]
+    pub(super) aliases: Vec<String>,
     pub(super) thanks: Option<String>,
     #[serde(default)]
-    pub(super) tags: Vec<String>,
+    pub(super) tags: Terms,
     #[serde(default)]
     pub(super) featured: bool,
     pub(super) layout: Option<String>,
@@ -25,19 +29,19 @@ pub(super) struct Metadata {
     pub(super) subscribe: Option<Subscribe>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug)]
 pub(super) struct Qualifiers {
     audience: Option<String>,
     epistemic: Option<String>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug)]
 pub(super) struct Subscribe {
     atom: Option<String>,
     json: Option<String>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug)]
 pub(super) struct Book {
     title: Option<String>,
     author: Option<String>,
@@ -52,7 +56,7 @@ pub(super) struct Book {
     review: Option<Review>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug)]
 pub(super) struct Review {
     rating: Rating,
     summary: String,
@@ -62,7 +66,7 @@ pub(super) struct Review {
 // but in fact it should be derived from the same text as its `Display`
 // implementation below. (A later enhancement: converting "****" etc. to it or
 // something cool like that.)
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug)]
 enum Rating {
     #[serde(rename = "Not recommended")]
     NotRecommended,
@@ -89,11 +93,104 @@ impl std::fmt::Display for Rating {
     }
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug)]
 pub(super) struct Series {
     // The name is optional: it could be supplied via the data file somewhere up
     // the tree.
-    name: Option<String>,
+    pub(super) name: Option<String>,
     // The *part* has to be supplied, though.
     part: u8,
 }
+
+/// The terms an item has in some taxonomy, e.g. its `tags`. Terms may be nested
+/// (see [`TermTree`]); whether nesting is *allowed* is up to the taxonomy.
+#[derive(Debug, Default)]
+pub(crate) struct Terms(pub(crate) Vec<TermTree>);
+
+impl Terms {
+    /// Parse a single term, a list of terms, or a list of nested terms:
+    ///
+    /// ```yaml
+    /// categories:
+    ///   - tech:
+    ///     - programming languages
+    ///     - agile software development
+    ///   - art
+    /// ```
+    pub(crate) fn from_value(value: &Value) -> Result<Terms, String> {
+        match value {
+            Value::Null => Ok(Terms::default()),
+            Value::Sequence(values) => values
+                .iter()
+                .map(TermTree::from_value)
+                .collect::<Result<Vec<_>, _>>()
+                .map(|trees| Terms(trees.into_iter().flatten().collect())),
+            value => TermTree::from_value(value).map(Terms),
+        }
+    }
+
+    /// The name of every term, including the parents of nested terms.
+    pub(crate) fn names(&self) -> Vec<String> {
+        let mut names = Vec::new();
+        for tree in &self.0 {
+            tree.collect_names(&mut names);
+        }
+        names
+    }
+}
+
+impl<'de> Deserialize<'de> for Terms {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Terms::from_value(&Value::deserialize(deserializer)?).map_err(D::Error::custom)
+    }
+}
+
+/// A term, along with any terms nested beneath it. An item with a nested term
+/// has every term along the way to it as well.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct TermTree {
+    pub(crate) name: String,
+    pub(crate) children: Vec<TermTree>,
+}
+
+impl TermTree {
+    /// A single entry in a list of terms can define several trees, since a
+    /// mapping may have more than one key.
+    fn from_value(value: &Value) -> Result<Vec<TermTree>, String> {
+        match value {
+            Value::Mapping(mapping) => mapping
+                .iter()
+                .map(|(name, children)| {
+                    Ok(TermTree {
+                        name: term_name(name)?,
+                        children: Terms::from_value(children)?.0,
+                    })
+                })
+                .collect(),
+            value => Ok(vec![TermTree {
+                name: term_name(value)?,
+                children: Vec::new(),
+            }]),
+        }
+    }
+
+    fn collect_names(&self, names: &mut Vec<String>) {
+        if !names.contains(&self.name) {
+            names.push(self.name.clone());
+        }
+        for child in &self.children {
+            child.collect_names(names);
+        }
+    }
+}
+
+fn term_name(value: &Value) -> Result<String, String> {
+    match value {
+        Value::String(name) => Ok(name.clone()),
+        Value::Number(number) => Ok(number.to_string()),
+        Value::Bool(b) => Ok(b.to_string()),
+        _ => Err(String::from(
+            "terms must be strings or nested lists of strings",
+        )),
+    }
+}
@@ -0,0 +1,766 @@
+//! Redirects in the [Netlify `_redirects` format][format]: a `_redirects` file
+//! at the root of the site, with one rule per line, e.g.
+//!
+//! ```text
+//! # Comments and blank lines are ignored.
+//! /journal/old-name/   /journal/new-name/   301!
+//! /appearances/*       /elsewhere/:splat    301
+//! ```
+//!
+//! Each rule is the path (or URL) to redirect from, any query parameters to
+//! match, the path (or URL) to redirect to, an optional status code (301 by
+//! default) which a trailing `!` forces even when there is a page at the path,
+//! and any conditions (e.g. `Country=us`). A `*` in the path matches anything,
+//! available to the target as `:splat`, and a `:name` segment matches any one
+//! segment, available to the target as `:name`.
+//!
+//! Items can add rules of their own with `aliases` in their front matter: each
+//! alias is a path the item used to live at, which redirects to it.
+//!
+//! The rules are checked against the rest of the site, regenerated into the
+//! output for hosts which understand the format, and optionally written as
+//! HTML pages which redirect with `<meta http-equiv="refresh">` for those
+//! which do not.
+//!
+//! [format]: https://docs.netlify.com/routing/redirects/
+
+use std::collections::{BTreeSet, HashSet};
+use std::fmt::{self, Display, Formatter};
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+use crate::error::{Error, Location};
+use crate::page::Page;
+
+/// The name of the redirects file, both at the root of the site and in the
+/// output.
+pub(crate) const FILE_NAME: &str = "_redirects";
+
+const DEFAULT_STATUS: u16 = 301;
+
+/// Redirect rules, in order: the first rule which matches a request wins.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Rules {
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Rule {
+    origin: Origin,
+    from: String,
+    /// Query parameters to match, e.g. `id=:id`.
+    query: Vec<String>,
+    to: String,
+    status: u16,
+    force: bool,
+    /// Conditions on the request, e.g. `Country=us`.
+    conditions: Vec<String>,
+}
+
+/// Where a rule came from, for reporting problems with it.
+#[derive(Debug, Clone, PartialEq)]
+enum Origin {
+    /// A (1-based) line in a redirects file.
+    File { path: PathBuf, line: usize },
+    /// One of the `aliases` of the item at `path`.
+    Alias { path: PathBuf },
+}
+
+impl Rules {
+    /// Load the redirects file in `site_root`, if there is one. Rules which do
+    /// not parse are left out and reported.
+    pub(crate) fn load(site_root: &Path) -> Result<(Option<Rules>, Vec<Error>), Error> {
+        let path = site_root.join(FILE_NAME);
+        match std::fs::read_to_string(&path) {
+            Ok(contents) => {
+                let (rules, errors) = Rules::parse(&path, &contents);
+                Ok((Some(rules), errors))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok((None, Vec::new())),
+            Err(e) => Err(Error::io(&path, e)),
+        }
+    }
+
+    pub(crate) fn parse(path: &Path, contents: &str) -> (Rules, Vec<Error>) {
+        let mut rules = Vec::new();
+        let mut errors = Vec::new();
+        for (index, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let origin = Origin::File {
+                path: path.to_owned(),
+                line: index + 1,
+            };
+            match Rule::parse(origin, line) {
+                Ok(rule) => rules.push(rule),
+                Err(message) => errors.push(Error::Redirect {
+                    path: path.to_owned(),
+                    location: Some(Location {
+                        line: index + 1,
+                        column: 1,
+                    }),
+                    message,
+                }),
+            }
+        }
+
+        (Rules { rules }, errors)
+    }
+
+    /// The rules from the site's redirects file (if it has one) followed by a
+    /// redirect from each of the `aliases` of every page to the page itself.
+    pub(crate) fn for_site(file: Option<&Rules>, pages: &[Page], config: &Config) -> Rules {
+        let mut rules = file.cloned().unwrap_or_default();
+        for page in pages {
+            let to = config.url_path_for(&page.metadata.slug);
+            for alias in &page.metadata.aliases {
+                rules.rules.push(Rule::alias(&page.source_path, alias, &to));
+            }
+        }
+        rules
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Check the rules against `outputs`, every file the build writes: a
+    /// redirect may not loop, a rule for a single path may not be hidden by a
+    /// page (unless it is forced) or by an earlier rule, and an alias may not
+    /// be the URL of a page. A rule whose target the build does not write only
+    /// gets a warning, since something else may serve it.
+    pub(crate) fn validate(
+        &self,
+        output_dir: &Path,
+        outputs: &BTreeSet<PathBuf>,
+    ) -> (Vec<Error>, Vec<String>) {
+        let pages: BTreeSet<String> = outputs
+            .iter()
+            .filter_map(|output| url_path(output_dir, output))
+            .collect();
+
+        let mut errors = Vec::new();
+        let mut warnings = Vec::new();
+        for (index, rule) in self.rules.iter().enumerate() {
+            if !rule.is_local() {
+                continue;
+            }
+
+            let page = pages
+                .iter()
+                .find(|page| rule.is_single_path() && rule.matches(page));
+            if let Some(page) = page {
+                match rule.origin {
+                    Origin::Alias { .. } => errors.push(rule.error(format!(
+                        "{} is the URL of the page at '{}'",
+                        rule.name(),
+                        page
+                    ))),
+                    Origin::File { .. } if rule.force => {}
+                    Origin::File { .. } => errors.push(rule.error(format!(
+                        "{} never applies, because there is a page at '{}' (add a `!` to the status to force it)",
+                        rule.name(),
+                        page
+                    ))),
+                }
+            }
+
+            let earlier = self.rules[..index]
+                .iter()
+                .find(|earlier| rule.is_single_path() && earlier.matches(&rule.from));
+            if let Some(earlier) = earlier {
+                errors.push(rule.error(format!(
+                    "{} never applies, because {} redirects it first",
+                    rule.name(),
+                    earlier.origin
+                )));
+            }
+
+            let target = match rule.exact_target() {
+                Some(target) => target,
+                None => continue,
+            };
+            if !rule.is_redirect() {
+                if !pages.contains(&page_path(target)) {
+                    warnings.push(format!(
+                        "{}: {} serves '{}', which does not exist",
+                        rule.origin,
+                        rule.name(),
+                        target
+                    ));
+                }
+            } else if self.follow(rule).is_none() {
+                errors.push(rule.error(format!("{} redirects in a loop", rule.name())));
+            } else if !pages.contains(&page_path(target))
+                && !self.rules.iter().any(|other| other.matches(target))
+            {
+                warnings.push(format!(
+                    "{}: {} redirects to '{}', which does not exist",
+                    rule.origin,
+                    rule.name(),
+                    target
+                ));
+            }
+        }
+
+        (errors, warnings)
+    }
+
+    /// Follow the chain of redirects starting at `rule` to wherever it ends, or
+    /// `None` if it never does. The chain ends at a rewrite or an error page,
+    /// since the host serves those rather than redirecting again.
+    fn follow<'r>(&'r self, rule: &'r Rule) -> Option<&'r str> {
+        let mut visited = HashSet::new();
+        let mut current = rule;
+        loop {
+            if !visited.insert(current as *const Rule) {
+                return None;
+            }
+            let target = match current.exact_target() {
+                Some(target) => target,
+                None => return Some(&current.to),
+            };
+            match self.rules.iter().find(|rule| rule.matches(target)) {
+                Some(next) if next.is_redirect() => current = next,
+                _ => return Some(target),
+            }
+        }
+    }
+
+    /// Regenerate the redirects file in the output directory, and write an
+    /// HTML redirect page for every alias, and for every other rule if the site
+    /// config asks for them. A redirect page never replaces any of the site's
+    /// other `outputs`.
+    pub(crate) fn write(&self, config: &Config, outputs: &BTreeSet<PathBuf>) -> Result<(), Error> {
+        let path = config.output.join(FILE_NAME);
+        std::fs::write(&path, self.to_string()).map_err(|e| Error::io(&path, e))?;
+
+        for rule in &self.rules {
+            let stub_path = rule
+                .stub_path(&config.output, config.redirects.html_stubs)
+                .filter(|path| !outputs.contains(path));
+            if let Some(path) = stub_path {
+                if let Some(containing_dir) = path.parent() {
+                    std::fs::create_dir_all(containing_dir).map_err(|e| Error::io(&path, e))?;
+                }
+                std::fs::write(&path, rule.stub(config)).map_err(|e| Error::io(&path, e))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for Rules {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for rule in &self.rules {
+            writeln!(f, "{}", rule)?;
+        }
+        Ok(())
+    }
+}
+
+impl Rule {
+    fn parse(origin: Origin, source: &str) -> Result<Rule, String> {
+        let mut fields = source.split_whitespace();
+        let from = fields.next().map(String::from).unwrap_or_default();
+
+        let mut query = Vec::new();
+        let to = loop {
+            match fields.next() {
+                Some(field) if is_path_or_url(field) => break field.to_string(),
+                Some(field) if field.contains('=') => query.push(field.to_string()),
+                Some(field) => return Err(format!("'{}' is not a path or URL", field)),
+                None => return Err(format!("'{}' has nowhere to redirect to", from)),
+            }
+        };
+        if !is_path_or_url(&from) {
+            return Err(format!("'{}' is not a path or URL", from));
+        }
+
+        let mut status = DEFAULT_STATUS;
+        let mut force = false;
+        let mut conditions = Vec::new();
+        for (index, field) in fields.enumerate() {
+            if index == 0 && !field.contains('=') {
+                let code = field.strip_suffix('!').unwrap_or(field);
+                force = code.len() < field.len();
+                status = match code.parse() {
+                    Ok(status @ 100..=599) => status,
+                    _ => return Err(format!("'{}' is not an HTTP status code", field)),
+                };
+            } else if field.contains('=') {
+                conditions.push(field.to_string());
+            } else {
+                return Err(format!("unexpected '{}' after the status code", field));
+            }
+        }
+
+        Ok(Rule {
+            origin,
+            from,
+            query,
+            to,
+            status,
+            force,
+            conditions,
+        })
+    }
+
+    /// A permanent redirect from `alias` to `to`, for the item at `path`.
+    fn alias(path: &Path, alias: &str, to: &str) -> Rule {
+        Rule {
+            origin: Origin::Alias {
+                path: path.to_owned(),
+            },
+            from: alias.to_string(),
+            query: Vec::new(),
+            to: to.to_string(),
+            status: DEFAULT_STATUS,
+            force: false,
+            conditions: Vec::new(),
+        }
+    }
+
+    /// How to refer to the rule in an error.
+    fn name(&self) -> String {
+        match self.origin {
+            Origin::File { .. } => format!("'{}'", self.from),
+            Origin::Alias { .. } => format!("the alias '{}'", self.from),
+        }
+    }
+
+    fn error(&self, message: String) -> Error {
+        match &self.origin {
+            Origin::File { path, line } => Error::Redirect {
+                path: path.clone(),
+                location: Some(Location {
+                    line: *line,
+                    column: 1,
+                }),
+                message,
+            },
+            Origin::Alias { path } => Error::Redirect {
+                path: path.clone(),
+                location: None,
+                message,
+            },
+        }
+    }
+
+    /// Whether the rule applies to paths on this site, rather than to a whole
+    /// domain, e.g. `https://example.netlify.app/*`.
+    fn is_local(&self) -> bool {
+        self.from.starts_with('/')
+    }
+
+    /// Whether the rule matches `path`, ignoring trailing slashes (as hosts
+    /// do). Rules with query parameters or conditions may not apply to every
+    /// request for a path, so they never match.
+    fn matches(&self, path: &str) -> bool {
+        self.is_local()
+            && self.query.is_empty()
+            && self.conditions.is_empty()
+            && glob_match(&normalize(&self.from), &normalize(path))
+    }
+
+    /// Whether the rule redirects, rather than rewriting (200) or serving an
+    /// error page (e.g. 404) from its target.
+    fn is_redirect(&self) -> bool {
+        matches!(self.status, 301 | 302 | 303 | 307 | 308)
+    }
+
+    /// Whether the rule is for one path, rather than a pattern with a `*` or
+    /// a `:name` segment.
+    fn is_single_path(&self) -> bool {
+        !self.from.contains('*') && !self.from.contains(':')
+    }
+
+    /// The path on this site the rule redirects to, unless it is somewhere else
+    /// entirely, or depends on what the rule matched (e.g. `:splat`).
+    fn exact_target(&self) -> Option<&str> {
+        if self.to.starts_with('/') && !self.to.contains(':') {
+            Some(&self.to)
+        } else {
+            None
+        }
+    }
+
+    /// Where the HTML redirect page for the rule goes, if it gets one: aliases
+    /// always do, and other rules do if the site config asks for `html_stubs`.
+    /// Only redirects (not rewrites or errors) from a single path can be done
+    /// with a static page.
+    fn stub_path(&self, output_dir: &Path, html_stubs: bool) -> Option<PathBuf> {
+        let wants_stub = html_stubs || matches!(self.origin, Origin::Alias { .. });
+        if !wants_stub
+            || !self.is_local()
+            || !self.is_redirect()
+            || !self.is_single_path()
+            || !self.query.is_empty()
+            || !self.conditions.is_empty()
+        {
+            return None;
+        }
+
+        let relative = self.from.trim_matches('/');
+        let file_name = relative.rsplit('/').next().unwrap_or_default();
+        if file_name.contains('.') && !self.from.ends_with('/') {
+            Some(output_dir.join(relative))
+        } else {
+            Some(output_dir.join(relative).join("index.html"))
+        }
+    }
+
+    /// An HTML page which redirects to the rule's target.
+    fn stub(&self, config: &Config) -> String {
+        let target = if self.to.starts_with('/') {
+            format!("{}{}", config.url.trim_end_matches('/'), self.to)
+        } else {
+            self.to.clone()
+        };
+        let target = escape(&target);
+        format!(
+            "<!DOCTYPE html>\n\
+             <html>\n\
+             <head>\n\
+             <meta charset=\"utf-8\">\n\
+             <title>Redirecting…</title>\n\
+             <link rel=\"canonical\" href=\"{0}\">\n\
+             <meta http-equiv=\"refresh\" content=\"0; url={0}\">\n\
+             <meta name=\"robots\" content=\"noindex\">\n\
+             </head>\n\
+             <body>\n\
+             <p>This page has moved to <a href=\"{0}\">{0}</a>.</p>\n\
+             </body>\n\
+             </html>\n",
+            target
+        )
+    }
+}
+
+impl Display for Origin {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Origin::File { path, line } => write!(f, "line {} of '{}'", line, path.display()),
+            Origin::Alias { path } => write!(f, "an alias in '{}'", path.display()),
+        }
+    }
+}
+
+impl Display for Rule {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.from)?;
+        for param in &self.query {
+            write!(f, " {}", param)?;
+        }
+        write!(
+            f,
+            " {} {}{}",
+            self.to,
+            self.status,
+            if self.force { "!" } else { "" }
+        )?;
+        for condition in &self.conditions {
+            write!(f, " {}", condition)?;
+        }
+        Ok(())
+    }
+}
+
+fn is_path_or_url(field: &str) -> bool {
+    field.starts_with('/') || field.starts_with("http://") || field.starts_with("https://")
+}
+
+/// Hosts treat `/path` and `/path/` the same when matching redirects.
+fn normalize(path: &str) -> String {
+    format!("/{}", path.trim_matches('/'))
+}
+
+/// Match `path` against a rule's `pattern`, where `*` matches anything at all
+/// and a `:name` segment matches any single segment.
+fn glob_match(pattern: &str, path: &str) -> bool {
+    match pattern.chars().next() {
+        None => path.is_empty(),
+        Some('*') => (0..=path.len())
+            .filter(|index| path.is_char_boundary(*index))
+            .any(|index| glob_match(&pattern[1..], &path[index..])),
+        Some('/') if pattern[1..].starts_with(':') => match path.strip_prefix('/') {
+            Some(path) => {
+                let name_end = pattern[1..].find('/').map_or(pattern.len(), |end| end + 1);
+                let segment_end = path.find('/').unwrap_or(path.len());
+                segment_end > 0 && glob_match(&pattern[name_end..], &path[segment_end..])
+            }
+            None => false,
+        },
+        Some(c) => match path.strip_prefix(c) {
+            Some(path) => glob_match(&pattern[c.len_utf8()..], path),
+            None => false,
+        },
+    }
+}
+
+/// The URL path at which a host serves `output`, a file in `output_dir`: pages
+/// are linked without their `.html` extension, and directories by their
+/// `index.html`.
+fn url_path(output_dir: &Path, output: &Path) -> Option<String> {
+    let relative = output.strip_prefix(output_dir).ok()?.to_str()?;
+    Some(page_path(&relative.replace(std::path::MAIN_SEPARATOR, "/")))
+}
+
+/// The URL path of the page at `relative`, a path in the output directory
+/// (or a rule's target) with or without its `index.html` or `.html`.
+fn page_path(relative: &str) -> String {
+    let relative = relative.trim_start_matches('/');
+    let path = if relative == "index.html" {
+        ""
+    } else if let Some(dir) = relative.strip_suffix("/index.html") {
+        dir
+    } else if let Some(page) = relative.strip_suffix(".html") {
+        page
+    } else {
+        relative
+    };
+    normalize(path)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = include_str!("../tests/sympolymathesy/_redirects");
+
+    fn sample() -> Rules {
+        parse(SAMPLE)
+    }
+
+    fn parse(source: &str) -> Rules {
+        let (rules, errors) = Rules::parse(Path::new("_redirects"), source);
+        assert!(errors.is_empty(), "{:?}", errors);
+        rules
+    }
+
+    #[test]
+    fn parses_the_sample_file() {
+        let rules = sample();
+        assert_eq!(rules.rules.len(), 76);
+        assert!(rules
+            .rules
+            .iter()
+            .all(|rule| rule.status == 301 && rule.force));
+
+        let domain = &rules.rules[0];
+        assert_eq!(
+            domain.origin,
+            Origin::File {
+                path: PathBuf::from("_redirects"),
+                line: 2
+            }
+        );
+        assert_eq!(domain.from, "http://v5-chriskrycho-com.netlify.com/*");
+        assert_eq!(domain.to, "https://v5.chriskrycho.com/:splat");
+        assert!(!domain.is_local());
+
+        let appearances = rules
+            .rules
+            .iter()
+            .find(|rule| rule.from == "/appearances/*")
+            .expect("has the splat rule");
+        assert!(appearances.matches("/appearances/2020/podcast/"));
+        assert!(!appearances.matches("/elsewhere/2020/podcast/"));
+        assert_eq!(appearances.exact_target(), None);
+
+        let this_week = rules
+            .rules
+            .iter()
+            .find(|rule| rule.from.starts_with("/journal/this-week-i-learned-"))
+            .expect("has the mid-segment splat rule");
+        assert!(this_week.matches("/journal/this-week-i-learned-3/"));
+        assert!(!this_week.matches("/journal/this-week-i-learned/3/"));
+    }
+
+    #[test]
+    fn regenerates_rules() {
+        let rules = sample();
+        let regenerated = rules.to_string();
+        assert!(regenerated.starts_with(
+            "http://v5-chriskrycho-com.netlify.com/* https://v5.chriskrycho.com/:splat 301!\n"
+        ));
+        assert!(regenerated.contains("\n/journal/relaunch! /journal/relaunch 301!\n"));
+
+        let (reparsed, errors) = Rules::parse(Path::new("_redirects"), &regenerated);
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(reparsed.to_string(), regenerated);
+
+        let rules = parse("/store id=:id /blog/:id 302 Country=us,ca\n/old /new\n");
+        assert_eq!(
+            rules.to_string(),
+            "/store id=:id /blog/:id 302 Country=us,ca\n/old /new 301\n"
+        );
+    }
+
+    #[test]
+    fn reports_invalid_rules() {
+        let (rules, errors) = Rules::parse(
+            Path::new("_redirects"),
+            "/fine /also-fine\n\n/nowhere\n/a /b 3O1\n/a /b 301 extra\nnot-a-path /b\n",
+        );
+        assert_eq!(rules.rules.len(), 1);
+        let lines: Vec<usize> = errors
+            .iter()
+            .map(|error| match error {
+                Error::Redirect {
+                    location: Some(location),
+                    ..
+                } => location.line,
+                other => panic!("expected a redirect error, got {:?}", other),
+            })
+            .collect();
+        assert_eq!(lines, vec![3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn validates_rules_against_the_site() {
+        let output = Path::new("/site/output");
+        let outputs: BTreeSet<PathBuf> = [
+            "index.html",
+            "journal/relaunch.html",
+            "journal/crew-dragon-fanfare/day-1/index.html",
+            "elsewhere.html",
+            "feed.json",
+        ]
+        .iter()
+        .map(|path| output.join(path))
+        .collect();
+
+        let rules = parse(
+            "/journal/relaunch! /journal/relaunch 301!\n\
+             /journal/crew-dragon-demo-2/ /journal/crew-dragon-fanfare/day-1/ 301!\n\
+             /appearances/* /elsewhere/:splat 301!\n\
+             /feeds/json /feed.json 301\n\
+             /journal/missing/ /journal/never-written/ 301\n\
+             /a /b\n\
+             /b/ /a/\n\
+             /elsewhere / 301!\n\
+             /journal/relaunch /journal/ 302\n",
+        );
+        let (errors, warnings) = rules.validate(output, &outputs);
+        let messages: Vec<String> = errors.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "_redirects: line 6, column 1: invalid redirect: '/a' redirects in a loop",
+                "_redirects: line 7, column 1: invalid redirect: '/b/' redirects in a loop",
+                "_redirects: line 9, column 1: invalid redirect: '/journal/relaunch' never applies, because there is a page at '/journal/relaunch' (add a `!` to the status to force it)",
+            ]
+        );
+        assert_eq!(
+            warnings,
+            vec![
+                "line 5 of '_redirects': '/journal/missing/' redirects to '/journal/never-written/', which does not exist",
+                "line 9 of '_redirects': '/journal/relaunch' redirects to '/journal/', which does not exist",
+            ]
+        );
+    }
+
+    #[test]
+    fn fallback_rules_are_not_hidden_by_pages_or_loops() {
+        let output = Path::new("/site/output");
+        let outputs: BTreeSet<PathBuf> = ["index.html", "404.html", "journal/index.html"]
+            .iter()
+            .map(|path| output.join(path))
+            .collect();
+
+        for contents in &["/* /index.html 200\n", "/* /404.html 404\n"] {
+            let (errors, warnings) = parse(contents).validate(output, &outputs);
+            assert!(errors.is_empty(), "{:?}", errors);
+            assert!(warnings.is_empty(), "{:?}", warnings);
+        }
+
+        let (errors, warnings) = parse("/* /missing.html 404\n").validate(output, &outputs);
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(
+            warnings,
+            vec!["line 1 of '_redirects': '/*' serves '/missing.html', which does not exist"]
+        );
+    }
+
+    #[test]
+    fn html_stubs_only_for_single_path_redirects() {
+        let output = Path::new("/site/output");
+        let rules = parse(
+            "/old/ /new/ 301\n\
+             /old.html /new/ 308!\n\
+             /appearances/* /elsewhere/:splat 301!\n\
+             /api/* https://api.example.com/:splat 200\n\
+             https://example.netlify.app/* https://example.com/:splat 301!\n",
+        );
+        let paths: Vec<Option<PathBuf>> = rules
+            .rules
+            .iter()
+            .map(|rule| rule.stub_path(output, true))
+            .collect();
+        assert_eq!(
+            paths,
+            vec![
+                Some(output.join("old/index.html")),
+                Some(output.join("old.html")),
+                None,
+                None,
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    fn aliases_redirect_to_their_pages() {
+        let output = Path::new("/site/output");
+        let outputs: BTreeSet<PathBuf> = ["notes/2020-04-29-0634.html", "about.html"]
+            .iter()
+            .map(|path| output.join(path))
+            .collect();
+
+        let mut rules = parse("/notes/0640/ /notes/2020-04-29-0634 301\n");
+        let page = Path::new("/site/content/notes/2020-04-29-0634.md");
+        for alias in &["/notes/0634/", "/notes/0640/", "/about"] {
+            rules
+                .rules
+                .push(Rule::alias(page, alias, "/notes/2020-04-29-0634"));
+        }
+
+        assert_eq!(
+            rules.to_string(),
+            "/notes/0640/ /notes/2020-04-29-0634 301\n\
+             /notes/0634/ /notes/2020-04-29-0634 301\n\
+             /notes/0640/ /notes/2020-04-29-0634 301\n\
+             /about /notes/2020-04-29-0634 301\n"
+        );
+
+        let (errors, warnings) = rules.validate(output, &outputs);
+        assert!(warnings.is_empty(), "{:?}", warnings);
+        let messages: Vec<String> = errors.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "/site/content/notes/2020-04-29-0634.md: invalid redirect: the alias '/notes/0640/' never applies, because line 1 of '_redirects' redirects it first",
+                "/site/content/notes/2020-04-29-0634.md: invalid redirect: the alias '/about' is the URL of the page at '/about'",
+            ]
+        );
+
+        // Aliases always get an HTML redirect page, whatever the config says.
+        assert_eq!(rules.rules[0].stub_path(output, false), None);
+        assert_eq!(
+            rules.rules[1].stub_path(output, false),
+            Some(output.join("notes/0634/index.html"))
+        );
+    }
+}
@@ -0,0 +1,285 @@
+//! Serve a built site locally, rebuilding it whenever its sources change and
+//! reloading any open pages once the rebuild is done.
+
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use percent_encoding::percent_decode_str;
+use tiny_http::{Header, Request, Response};
+
+use crate::config::Config;
+use crate::error::Error;
+
+/// The path pages poll to find out whether the site has been rebuilt.
+const GENERATION_PATH: &str = "/__lx/generation";
+
+/// Injected into every HTML page: polls the server for the current build
+/// generation, and reloads the page whenever it changes.
+const LIVE_RELOAD: &str = r#"<script>
+(() => {
+  let generation = null;
+  setInterval(async () => {
+    try {
+      const current = await (await fetch("/__lx/generation")).text();
+      if (generation !== null && current !== generation) location.reload();
+      generation = current;
+    } catch (_) {}
+  }, 500);
+})();
+</script>"#;
+
+/// Build the site in `site_directory`, serve it on `port`, and rebuild it
+/// whenever anything in the site changes (see [`crate::watch`]). Runs until
+/// killed.
+pub fn serve(site_directory: PathBuf, port: u16, use_cache: bool) -> Result<(), Error> {
+    let site_directory =
+        std::fs::canonicalize(&site_directory).map_err(|e| Error::io(&site_directory, e))?;
+    let config = Config::from_file(&site_directory.join("_data/config.json5"))?;
+
+    let server = Server::new(&config.output, &format!("127.0.0.1:{}", port))?;
+    println!(
+        "serving {} at http://{}",
+        site_directory.display(),
+        server.address()
+    );
+
+    let generation = server.generation();
+    std::thread::spawn(move || {
+        let watched = crate::watch::run(site_directory, use_cache, || {
+            generation.fetch_add(1, Ordering::SeqCst);
+        });
+        if let Err(e) = watched {
+            eprintln!("{}", e);
+        }
+    });
+
+    server.run();
+    Ok(())
+}
+
+/// An HTTP server for the built output of a site.
+pub struct Server {
+    http: tiny_http::Server,
+    root: PathBuf,
+    /// Incremented after every rebuild, so pages know when to reload.
+    generation: Arc<AtomicUsize>,
+}
+
+impl Server {
+    pub fn new(root: &Path, address: &str) -> Result<Server, Error> {
+        let http = tiny_http::Server::http(address).map_err(|e| Error::Serve {
+            address: String::from(address),
+            message: e.to_string(),
+        })?;
+        Ok(Server {
+            http,
+            root: root.to_owned(),
+            generation: Arc::new(AtomicUsize::new(0)),
+        })
+    }
+
+    pub fn address(&self) -> String {
+        self.http.server_addr().to_string()
+    }
+
+    pub fn generation(&self) -> Arc<AtomicUsize> {
+        Arc::clone(&self.generation)
+    }
+
+    /// Handle requests until the server is shut down.
+    pub fn run(&self) {
+        for request in self.http.incoming_requests() {
+            let url = request.url().to_string();
+            if let Err(e) = self.respond(request) {
+                eprintln!("{}: {}", url, e);
+            }
+        }
+    }
+
+    fn respond(&self, request: Request) -> std::io::Result<()> {
+        let path = request.url().split(['?', '#']).next().unwrap_or("/");
+
+        if path == GENERATION_PATH {
+            let generation = self.generation.load(Ordering::SeqCst).to_string();
+            return request.respond(with_type(Response::from_string(generation), "text/plain"));
+        }
+
+        match self.resolve(path) {
+            Some(file) => {
+                let contents = std::fs::read(&file)?;
+                let mime_type = mime_type(&file);
+                let contents = if mime_type.starts_with("text/html") {
+                    inject_live_reload(contents)
+                } else {
+                    contents
+                };
+                request.respond(with_type(Response::from_data(contents), mime_type))
+            }
+            None => {
+                let not_found = self.root.join("404.html");
+                let response = match std::fs::read(&not_found) {
+                    Ok(contents) => with_type(
+                        Response::from_data(inject_live_reload(contents)),
+                        mime_type(&not_found),
+                    ),
+                    Err(_) => with_type(
+                        Response::from_data(format!("not found: {}", path).into_bytes()),
+                        "text/plain",
+                    ),
+                };
+                request.respond(response.with_status_code(404))
+            }
+        }
+    }
+
+    /// Find the file to serve for a URL path. Pages are written as `<slug>.html`
+    /// but linked as `<slug>`, and directories are served by their
+    /// `index.html`.
+    fn resolve(&self, url_path: &str) -> Option<PathBuf> {
+        let decoded = percent_decode_str(url_path).decode_utf8().ok()?;
+        let relative = Path::new(decoded.trim_start_matches('/'));
+
+        // Never serve anything outside the output directory.
+        if relative
+            .components()
+            .any(|component| !matches!(component, Component::Normal(_)))
+        {
+            return None;
+        }
+
+        let path = self.root.join(relative);
+        vec![
+            path.clone(),
+            path.join("index.html"),
+            path.with_extension("html"),
+        ]
+        .into_iter()
+        .find(|candidate| candidate.is_file())
+    }
+}
+
+fn with_type<R: std::io::Read>(response: Response<R>, mime_type: &str) -> Response<R> {
+    let header = Header::from_bytes("Content-Type", mime_type)
+        .expect("MIME types are always valid header values");
+    response.with_header(header)
+}
+
+fn inject_live_reload(contents: Vec<u8>) -> Vec<u8> {
+    let html = String::from_utf8_lossy(&contents);
+    let injected = match html.rfind("</body>") {
+        Some(index) => format!("{}{}{}", &html[..index], LIVE_RELOAD, &html[index..]),
+        None => format!("{}{}", html, LIVE_RELOAD),
+    };
+    injected.into_bytes()
+}
+
+fn mime_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("html") | Some("htm") => "text/html; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("js") | Some("mjs") => "text/javascript; charset=utf-8",
+        Some("json") => "application/json",
+        Some("xml") => "application/xml",
+        Some("txt") => "text/plain; charset=utf-8",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("ico") => "image/x-icon",
+        Some("woff") => "font/woff",
+        Some("woff2") => "font/woff2",
+        Some("pdf") => "application/pdf",
+        Some("mp3") => "audio/mpeg",
+        Some("mp4") => "video/mp4",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+
+    use super::*;
+    use crate::test_site::TestSite;
+
+    /// Serve a directory of output on a background thread, returning the
+    /// directory (which goes away with it), the address, and the generation.
+    fn serve_dir(name: &str) -> (TestSite, String, Arc<AtomicUsize>) {
+        let dir = TestSite::empty(&format!("serve-{}", name));
+        dir.write(&[
+            ("index.html", "<html><body>home</body></html>"),
+            ("journal/2020/hello world.html", "<body>hello</body>"),
+            ("light.css", "body {}"),
+            ("feed.json", "{}"),
+        ]);
+
+        let server = Server::new(&dir.root, "127.0.0.1:0").expect("starts server");
+        let address = server.address();
+        let generation = server.generation();
+        std::thread::spawn(move || server.run());
+        (dir, address, generation)
+    }
+
+    /// A minimal HTTP/1.0 client: returns the status code, the content type,
+    /// and the body.
+    fn get(address: &str, path: &str) -> (u16, String, String) {
+        let mut stream = TcpStream::connect(address).expect("connects");
+        write!(stream, "GET {} HTTP/1.0\r\nHost: {}\r\n\r\n", path, address).unwrap();
+        let mut response = String::new();
+        stream
+            .read_to_string(&mut response)
+            .expect("reads response");
+
+        let (head, body) = response.split_once("\r\n\r\n").expect("has a body");
+        let status = head[9..12].parse().expect("has a status");
+        let content_type = head
+            .lines()
+            .find_map(|line| line.strip_prefix("Content-Type: "))
+            .unwrap_or_default()
+            .to_string();
+        (status, content_type, body.to_string())
+    }
+
+    #[test]
+    fn serves_files_with_mime_types() {
+        let (_dir, address, _) = serve_dir("mime");
+
+        let (status, content_type, body) = get(&address, "/light.css");
+        assert_eq!(
+            (status, content_type.as_str(), body.as_str()),
+            (200, "text/css; charset=utf-8", "body {}")
+        );
+
+        let (status, content_type, _) = get(&address, "/feed.json");
+        assert_eq!((status, content_type.as_str()), (200, "application/json"));
+    }
+
+    #[test]
+    fn resolves_slugs_and_indexes() {
+        let (_dir, address, _) = serve_dir("resolve");
+
+        let (status, content_type, body) = get(&address, "/");
+        assert_eq!(status, 200);
+        assert_eq!(content_type, "text/html; charset=utf-8");
+        assert!(body.starts_with("<html><body>home<script>"));
+        assert!(body.ends_with("</script></body></html>"));
+
+        let (status, _, body) = get(&address, "/journal/2020/hello%20world");
+        assert_eq!(status, 200);
+        assert!(body.starts_with("<body>hello"));
+
+        assert_eq!(get(&address, "/journal/2020/missing").0, 404);
+        assert_eq!(get(&address, "/../etc/passwd").0, 404);
+    }
+
+    #[test]
+    fn reports_the_build_generation() {
+        let (_dir, address, generation) = serve_dir("generation");
+        assert_eq!(get(&address, GENERATION_PATH).2, "0");
+        generation.fetch_add(1, Ordering::SeqCst);
+        assert_eq!(get(&address, "/__lx/generation?since=0").2, "1");
+    }
+}
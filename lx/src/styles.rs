@@ -0,0 +1,160 @@
+//! Compile the site's Sass stylesheets into CSS.
+//!
+//! Every `.scss` or `.sass` file in the `styles.directory` from the site config
+//! is an entry point, except for partials (files whose names start with `_`),
+//! which only exist to be `@use`d or `@import`ed by others. Each one is compiled
+//! to the same path under `styles.output` in the output, e.g.
+//! `_ui/styles/print.scss` to `styles/print.css`. The compiled stylesheets are
+//! then assets like any other (see [`crate::assets`]), so layouts link to them
+//! with `asset()` and they are fingerprinted along with the rest.
+//!
+//! `@use` and `@import` look for files relative to the file doing the importing
+//! first, then relative to the site root. Release builds are minified;
+//! development builds (`lx watch` and `lx serve`) are not, so they are easier
+//! to read in the browser's tools.
+
+use std::path::{Path, PathBuf};
+
+use rayon::prelude::*;
+
+use crate::build::{files_under, Mode};
+use crate::config::Config;
+use crate::error::Error;
+
+/// Compile every stylesheet for the site at `site_root`, returning the CSS for
+/// each by its path in the output, and errors for any which failed to compile.
+pub(crate) fn compile(
+    site_root: &Path,
+    config: &Config,
+    mode: Mode,
+) -> (Vec<(String, String)>, Vec<Error>) {
+    let directory = match &config.styles.directory {
+        Some(directory) => directory,
+        None => return (Vec::new(), Vec::new()),
+    };
+
+    let style = match mode {
+        Mode::Release => grass::OutputStyle::Compressed,
+        Mode::Development => grass::OutputStyle::Expanded,
+    };
+
+    let entry_points = match entry_points(directory) {
+        Ok(entry_points) => entry_points,
+        Err(e) => return (Vec::new(), vec![e]),
+    };
+
+    entry_points
+        .par_iter()
+        .map(|path| {
+            // The options hold the file system and logger as trait objects, so
+            // cannot be shared between threads.
+            let options = grass::Options::default().style(style).load_path(site_root);
+            let css = grass::from_path(path, &options).map_err(|e| Error::style(path, *e))?;
+            Ok((output_name(directory, path, &config.styles.output), css))
+        })
+        .partition_map(|result| match result {
+            Ok(compiled) => rayon::iter::Either::Left(compiled),
+            Err(e) => rayon::iter::Either::Right(e),
+        })
+}
+
+/// Whether the file at `path` is a Sass source, i.e. whether changing it
+/// means compiling the stylesheets again.
+pub(crate) fn is_source(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|extension| extension.to_str()),
+        Some("scss") | Some("sass")
+    )
+}
+
+fn entry_points(directory: &Path) -> Result<Vec<PathBuf>, Error> {
+    Ok(files_under(directory, "**/*")?
+        .into_iter()
+        .filter(|path| path.is_file() && is_source(path))
+        .filter(|path| {
+            path.file_name()
+                .is_some_and(|name| !name.to_string_lossy().starts_with('_'))
+        })
+        .collect())
+}
+
+/// The path in the output for the entry point at `path`, e.g. `styles/a/b.css`
+/// for `<directory>/a/b.scss`.
+fn output_name(directory: &Path, path: &Path, output: &Path) -> String {
+    let relative = path.strip_prefix(directory).unwrap_or(path);
+    output
+        .join(relative)
+        .with_extension("css")
+        .to_string_lossy()
+        .replace('\\', "/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_site::TestSite;
+
+    const STYLES: &str = "styles: { directory: \"_ui/styles\" }";
+
+    #[test]
+    fn compiles_entry_points_but_not_partials() {
+        let site = TestSite::new("styles-compile", STYLES);
+        site.write(&[
+            (
+                "_ui/styles/style.scss",
+                "@use 'config';\n@import '_ui/shared/reset';\nbody { color: config.$ink; }",
+            ),
+            ("_ui/styles/_config.scss", "$ink: #123456;"),
+            ("_ui/styles/print/print.sass", "body\n  color: black"),
+            ("_ui/shared/_reset.scss", "* { margin: 0; }"),
+        ]);
+        let config = site.config();
+
+        let (mut compiled, errors) = compile(&site.root, &config, Mode::Development);
+        assert!(errors.is_empty(), "{:?}", errors);
+        compiled.sort();
+        let names: Vec<&str> = compiled.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["styles/print/print.css", "styles/style.css"]);
+        assert_eq!(
+            compiled[1].1,
+            "* {\n  margin: 0;\n}\n\nbody {\n  color: #123456;\n}\n"
+        );
+
+        let (compiled, _) = compile(&site.root, &config, Mode::Release);
+        assert!(compiled
+            .iter()
+            .any(|(_, css)| css == "*{margin:0}body{color:#123456}"));
+    }
+
+    #[test]
+    fn reports_errors_where_they_are() {
+        let site = TestSite::new("styles-errors", STYLES);
+        site.write(&[
+            (
+                "_ui/styles/style.scss",
+                "@use 'colors';\nbody { color: red; }",
+            ),
+            (
+                "_ui/styles/_colors.scss",
+                "$ink: #123456;\n\n.a { color: $nope; }",
+            ),
+        ]);
+        let config = site.config();
+
+        let (compiled, errors) = compile(&site.root, &config, Mode::Release);
+        assert!(compiled.is_empty());
+        assert_eq!(errors.len(), 1);
+        match &errors[0] {
+            Error::Style {
+                path,
+                location: Some(location),
+                message,
+            } => {
+                assert!(path.ends_with("_ui/styles/_colors.scss"), "{:?}", path);
+                assert_eq!((location.line, location.column), (3, 13));
+                assert_eq!(message, "Undefined variable.");
+            }
+            other => panic!("expected a style error, got {:?}", other),
+        }
+    }
+}
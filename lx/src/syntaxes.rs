@@ -0,0 +1,117 @@
+//! The syntax definitions used for highlighting code: syntect's defaults, plus
+//! any `.sublime-syntax` files in the site's `syntaxes.directory`, for
+//! languages syntect does not know about (e.g. TypeScript or Elm). A site
+//! definition with the same name as a default one comes after it, so code
+//! blocks which name the language by a file extension get the site's.
+//!
+//! Parsing and linking the definitions is a noticeable part of starting a
+//! build, so with `syntaxes.dump` set, the combined set is also stored in the
+//! build cache (see [`crate::cache`]) and loaded from there as long as the
+//! site's definitions have not changed.
+
+use std::path::{Path, PathBuf};
+
+use syntect::parsing::{SyntaxDefinition, SyntaxSet};
+
+use crate::build::files_under;
+use crate::cache::Cache;
+use crate::config::Config;
+use crate::error::Error;
+
+/// Load the syntax definitions for the site, from the `cache` if there is one
+/// and the site config allows it. Definitions which cannot be loaded are left
+/// out and reported.
+pub(crate) fn load(config: &Config, cache: Option<&Cache>) -> (SyntaxSet, Vec<Error>) {
+    let cache = cache.filter(|_| config.syntaxes.dump);
+    if let Some(syntax_set) = cache.and_then(Cache::get_syntaxes) {
+        return (syntax_set, Vec::new());
+    }
+
+    let mut builder = SyntaxSet::load_defaults_newlines().into_builder();
+    let (paths, mut errors) = match files(config) {
+        Ok(paths) => (paths, Vec::new()),
+        Err(e) => (Vec::new(), vec![e]),
+    };
+    for path in paths {
+        match parse(&path) {
+            Ok(syntax) => builder.add(syntax),
+            Err(e) => errors.push(e),
+        }
+    }
+    let syntax_set = builder.build();
+
+    // Only a complete set is worth keeping: otherwise the errors would go away
+    // on the next build, along with the broken definitions.
+    if let Some(cache) = cache {
+        if errors.is_empty() {
+            cache.put_syntaxes(&syntax_set);
+        }
+    }
+
+    (syntax_set, errors)
+}
+
+/// Every syntax definition file in the site's syntaxes directory.
+pub(crate) fn files(config: &Config) -> Result<Vec<PathBuf>, Error> {
+    files_under(&config.syntaxes.directory, "**/*.sublime-syntax")
+}
+
+fn parse(path: &Path) -> Result<SyntaxDefinition, Error> {
+    let contents = std::fs::read_to_string(path).map_err(|e| Error::io(path, e))?;
+    let fallback_name = path.file_stem().and_then(|stem| stem.to_str());
+    SyntaxDefinition::load_from_str(&contents, true, fallback_name).map_err(|e| Error::Syntax {
+        path: path.to_owned(),
+        message: e.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_site::TestSite;
+
+    const ELM: &str = "%YAML 1.2
+---
+name: Elm
+file_extensions: [elm]
+scope: source.elm
+contexts:
+  main:
+    - match: '\\b(module|import|exposing)\\b'
+      scope: keyword.other.elm
+";
+
+    #[test]
+    fn loads_site_syntaxes_and_reports_broken_ones() {
+        let site = TestSite::new("syntaxes", "syntaxes: { dump: true }");
+        site.write(&[
+            ("_syntaxes/Elm.sublime-syntax", ELM),
+            (
+                "_syntaxes/broken/Nope.sublime-syntax",
+                "name: Nope\ncontexts: [",
+            ),
+        ]);
+
+        let config = site.config();
+        let cache = Cache::open(&site.root, String::from("test")).expect("opens the cache");
+        let (syntax_set, errors) = load(&config, Some(&cache));
+        assert!(syntax_set.find_syntax_by_token("elm").is_some());
+        assert!(syntax_set.find_syntax_by_token("rust").is_some());
+        assert_eq!(errors.len(), 1);
+        assert!(
+            errors[0].to_string().contains("broken/Nope.sublime-syntax"),
+            "{}",
+            errors[0]
+        );
+        assert!(
+            cache.get_syntaxes().is_none(),
+            "does not cache a broken set"
+        );
+
+        std::fs::remove_file(site.root.join("_syntaxes/broken/Nope.sublime-syntax")).unwrap();
+        let (_, errors) = load(&config, Some(&cache));
+        assert!(errors.is_empty());
+        let cached = cache.get_syntaxes().expect("caches the set");
+        assert!(cached.find_syntax_by_token("elm").is_some());
+    }
+}
@@ -0,0 +1,673 @@
+//! Group pages by the terms of each taxonomy in the site config, and render a
+//! page listing each taxonomy's terms and a page for each term listing its
+//! items.
+
+pub(crate) mod archive;
+
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use serde_derive::Serialize;
+use serde_yaml::Value;
+use tera::Context;
+
+use crate::config::{Config, Taxonomy, TaxonomyKind};
+use crate::error::Error;
+use crate::feed::Feed;
+use crate::layout::{Layouts, PageLink};
+use crate::page::metadata::{self, TermTree};
+use crate::page::Page;
+
+/// All the terms for a single taxonomy, and the pages which have them.
+pub(crate) struct Terms<'a> {
+    taxonomy: &'a Taxonomy,
+    /// Every term, keyed by its slug, so that e.g. `Rust` and `rust` are the
+    /// same term. The name is whichever spelling appeared first. The slug of a
+    /// nested term includes its parents', e.g. `tech/programming-languages`.
+    terms: BTreeMap<String, Term<'a>>,
+}
+
+struct Term<'a> {
+    name: String,
+    /// The slug of the term this one is nested under, if any.
+    parent: Option<String>,
+    pages: Vec<&'a Page>,
+}
+
+/// Assign every page to its terms in every taxonomy, reporting any page which
+/// violates its taxonomy's `required`, `limit`, or `hierarchical` rules.
+pub(crate) fn assign<'a>(config: &'a Config, pages: &'a [Page]) -> (Vec<Terms<'a>>, Vec<Error>) {
+    let mut all_terms = Vec::with_capacity(config.taxonomies.len());
+    let mut errors = Vec::new();
+
+    for taxonomy in &config.taxonomies {
+        // Temporal taxonomies group by date rather than by term: see `archive`.
+        if taxonomy.kind == TaxonomyKind::Temporal {
+            continue;
+        }
+
+        let mut terms = Terms {
+            taxonomy,
+            terms: BTreeMap::new(),
+        };
+
+        for page in pages {
+            match terms_for(taxonomy, page) {
+                Ok(trees) => {
+                    for tree in &trees {
+                        terms.add(tree, None, page);
+                    }
+                }
+                Err(e) => errors.push(e),
+            }
+        }
+
+        for term in terms.terms.values_mut() {
+            term.pages.sort_by(|a, b| {
+                b.metadata
+                    .date()
+                    .cmp(&a.metadata.date())
+                    .then_with(|| a.metadata.title().cmp(&b.metadata.title()))
+            });
+        }
+
+        all_terms.push(terms);
+    }
+
+    (all_terms, errors)
+}
+
+/// The terms `page` has in `taxonomy`, after applying the taxonomy's default
+/// and checking its rules.
+fn terms_for(taxonomy: &Taxonomy, page: &Page) -> Result<Vec<TermTree>, Error> {
+    let path = &page.source_path;
+    let value = page
+        .metadata
+        .front_matter
+        .get(taxonomy.key())
+        .filter(|value| !value.is_null());
+
+    let mut trees = match value {
+        Some(value) => parse_terms(taxonomy, path, value)?,
+        None => Vec::new(),
+    };
+
+    if trees.is_empty() {
+        if let Some(default) = &taxonomy.default {
+            trees = parse_terms(taxonomy, path, default)?;
+        }
+    }
+
+    if trees.is_empty() && taxonomy.required {
+        return Err(Error::MissingField {
+            path: path.clone(),
+            field: format!("{} (for taxonomy '{}')", taxonomy.key(), taxonomy.name),
+        });
+    }
+
+    if let Some(limit) = taxonomy.limit {
+        if trees.len() > limit {
+            return Err(Error::invalid_front_matter(
+                path,
+                format!(
+                    "taxonomy '{}' allows at most {} terms, but found {}",
+                    taxonomy.name,
+                    limit,
+                    trees.len()
+                ),
+            ));
+        }
+    }
+
+    // A term needs a slug for its page, e.g. `!!!` has nothing to use.
+    if let Some(name) = trees.iter().find_map(unsluggable) {
+        return Err(Error::invalid_front_matter(
+            path,
+            format!(
+                "taxonomy '{}': the term '{}' has no letters or numbers to use in its URL",
+                taxonomy.name, name
+            ),
+        ));
+    }
+
+    if !taxonomy.hierarchical && trees.iter().any(|tree| !tree.children.is_empty()) {
+        return Err(Error::invalid_front_matter(
+            path,
+            format!(
+                "taxonomy '{}' is not hierarchical, so its terms cannot be nested",
+                taxonomy.name
+            ),
+        ));
+    }
+
+    Ok(trees)
+}
+
+/// The name of the first term in `tree` whose slug would be empty, if any.
+fn unsluggable(tree: &TermTree) -> Option<&str> {
+    if slug::slugify(&tree.name).is_empty() {
+        Some(&tree.name)
+    } else {
+        tree.children.iter().find_map(unsluggable)
+    }
+}
+
+fn parse_terms(taxonomy: &Taxonomy, path: &Path, value: &Value) -> Result<Vec<TermTree>, Error> {
+    match taxonomy.kind {
+        TaxonomyKind::Binary => match value {
+            Value::Bool(true) => Ok(vec![TermTree {
+                name: taxonomy.name.clone(),
+                children: Vec::new(),
+            }]),
+            Value::Bool(false) => Ok(Vec::new()),
+            _ => Err(Error::invalid_front_matter(
+                path,
+                format!("taxonomy '{}' must be `true` or `false`", taxonomy.name),
+            )),
+        },
+        TaxonomyKind::Multiple | TaxonomyKind::Temporal => metadata::Terms::from_value(value)
+            .map(|terms| terms.0)
+            .map_err(|e| {
+                Error::invalid_front_matter(path, format!("taxonomy '{}': {}", taxonomy.name, e))
+            }),
+    }
+}
+
+#[derive(Serialize)]
+struct TaxonomyContext<'a> {
+    name: &'a str,
+    url: String,
+    /// The top-level terms; any nested terms are their `children`.
+    terms: Vec<TermContext<'a>>,
+}
+
+#[derive(Serialize)]
+struct TermContext<'a> {
+    name: &'a str,
+    slug: &'a str,
+    url: String,
+    count: usize,
+    pages: Vec<PageLink<'a>>,
+    children: Vec<TermContext<'a>>,
+}
+
+impl<'a> Terms<'a> {
+    /// The path to the taxonomy, relative to the root of the site.
+    fn slug(&self) -> String {
+        slug::slugify(&self.taxonomy.name)
+    }
+
+    /// The path to a term, relative to the root of the site. The sole term of
+    /// a binary taxonomy lives at the root of the taxonomy, unless the list
+    /// page is already there.
+    fn term_slug(&self, term_slug: &str) -> String {
+        match self.taxonomy.kind {
+            TaxonomyKind::Binary if self.taxonomy.templates.list.is_none() => self.slug(),
+            _ => format!("{}/{}", self.slug(), term_slug),
+        }
+    }
+
+    /// Add `page` to the term at the root of `tree` and to every term nested
+    /// within it.
+    fn add(&mut self, tree: &TermTree, parent: Option<&str>, page: &'a Page) {
+        let slug = term_key(parent, &tree.name);
+        let term = self.terms.entry(slug.clone()).or_insert_with(|| Term {
+            name: tree.name.clone(),
+            parent: parent.map(String::from),
+            pages: Vec::new(),
+        });
+
+        // The same term can show up more than once in a single item's trees,
+        // e.g. `[tech: [rust], tech: [agile]]`.
+        if !term
+            .pages
+            .iter()
+            .any(|existing| std::ptr::eq(*existing, page))
+        {
+            term.pages.push(page);
+        }
+
+        for child in &tree.children {
+            self.add(child, Some(&slug), page);
+        }
+    }
+
+    /// Template data for the terms nested directly under `parent` (or the
+    /// top-level terms, for `None`) and, recursively, their children. Item
+    /// pages need each term's pages; the list page only links to the terms, so
+    /// it can skip them.
+    fn terms_context(
+        &self,
+        config: &'a Config,
+        parent: Option<&str>,
+        with_pages: bool,
+    ) -> Vec<TermContext<'_>> {
+        self.terms
+            .iter()
+            .filter(|(_, term)| term.parent.as_deref() == parent)
+            .map(|(slug, term)| self.term_context(config, slug, term, with_pages))
+            .collect()
+    }
+
+    fn term_context(
+        &self,
+        config: &'a Config,
+        slug: &'a str,
+        term: &'a Term,
+        with_pages: bool,
+    ) -> TermContext<'_> {
+        TermContext {
+            name: &term.name,
+            slug,
+            url: config.url_for(&self.term_slug(slug)),
+            count: term.pages.len(),
+            pages: if with_pages {
+                term.pages
+                    .iter()
+                    .map(|page| PageLink::new(page, config))
+                    .collect()
+            } else {
+                Vec::new()
+            },
+            children: self.terms_context(config, Some(slug), false),
+        }
+    }
+
+    /// The keys of every term which includes any of the `changed` pages.
+    fn affected(&self, changed: &[&Page]) -> HashSet<String> {
+        fn collect(tree: &TermTree, parent: Option<&str>, keys: &mut HashSet<String>) {
+            let key = term_key(parent, &tree.name);
+            for child in &tree.children {
+                collect(child, Some(&key), keys);
+            }
+            keys.insert(key);
+        }
+
+        let mut keys = HashSet::new();
+        for page in changed {
+            for tree in terms_for(self.taxonomy, page).unwrap_or_default() {
+                collect(&tree, None, &mut keys);
+            }
+        }
+        keys
+    }
+
+    /// Render the list page (if the taxonomy has one) and every term page,
+    /// plus a feed for each term if the taxonomy asks for them. If `changed` is
+    /// set, render only the terms which include (or included) those pages, and
+    /// remove the pages for any such terms which no longer have any items.
+    pub(crate) fn write(
+        &self,
+        layouts: &Layouts,
+        config: &'a Config,
+        changed: Option<&[&Page]>,
+    ) -> Result<(), Error> {
+        let affected = changed.map(|changed| self.affected(changed));
+        let is_affected = |slug: &str| match &affected {
+            Some(keys) => keys.contains(slug),
+            None => true,
+        };
+        if matches!(&affected, Some(keys) if keys.is_empty()) {
+            return Ok(());
+        }
+
+        for removed in affected
+            .iter()
+            .flatten()
+            .filter(|key| !self.terms.contains_key(*key))
+        {
+            remove_html(&config.output_path(&self.term_slug(removed)))?;
+        }
+
+        if let Some(list_template) = &self.taxonomy.templates.list {
+            let mut context = Context::new();
+            context.insert("config", config);
+            context.insert(
+                "taxonomy",
+                &TaxonomyContext {
+                    name: &self.taxonomy.name,
+                    url: config.url_for(&self.slug()),
+                    terms: self.terms_context(config, None, false),
+                },
+            );
+
+            let path = config.output_path(&self.slug());
+            let rendered = layouts
+                .render(list_template, &context)
+                .map_err(|e| e.rendering(&path))?;
+            write_html(&path, rendered)?;
+        }
+
+        for (slug, term) in self.terms.iter().filter(|(slug, _)| is_affected(slug)) {
+            let term = self.term_context(config, slug, term, true);
+            let mut context = Context::new();
+            context.insert("config", config);
+            context.insert("taxonomy", &self.taxonomy.name);
+            context.insert("term", &term);
+
+            let path = config.output_path(&self.term_slug(term.slug));
+            let rendered = layouts
+                .render(&self.taxonomy.templates.item, &context)
+                .map_err(|e| e.rendering(&path))?;
+            write_html(&path, rendered)?;
+        }
+
+        if self.taxonomy.generate_feeds {
+            for (slug, term) in self.terms.iter().filter(|(slug, _)| is_affected(slug)) {
+                self.feed(config, slug, term).write(&config.output)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn feed(&self, config: &'a Config, slug: &str, term: &Term<'a>) -> Feed<'a> {
+        Feed::new(
+            format!("{}: {}", config.title.normal, term.name),
+            self.term_slug(slug),
+            config.feeds.engines.clone(),
+            config,
+            term.pages.iter().copied(),
+        )
+    }
+
+    /// Every file [`Terms::write`] writes when writing everything.
+    pub(crate) fn output_paths(&self, config: &'a Config) -> Vec<PathBuf> {
+        let mut paths = Vec::new();
+        if self.taxonomy.templates.list.is_some() {
+            paths.push(config.output_path(&self.slug()));
+        }
+        for (slug, term) in &self.terms {
+            paths.push(config.output_path(&self.term_slug(slug)));
+            if self.taxonomy.generate_feeds {
+                paths.extend(self.feed(config, slug, term).output_paths(&config.output));
+            }
+        }
+        paths
+    }
+
+    /// What writes the taxonomy's pages, for reporting collisions.
+    pub(crate) fn describe(&self) -> String {
+        format!("the '{}' taxonomy", self.taxonomy.name)
+    }
+}
+
+/// The key for a term: its slug, nested under its parent's key if it has one.
+fn term_key(parent: Option<&str>, name: &str) -> String {
+    match parent {
+        Some(parent) => format!("{}/{}", parent, slug::slugify(name)),
+        None => slug::slugify(name),
+    }
+}
+
+fn write_html(path: &Path, rendered: String) -> Result<(), Error> {
+    if let Some(containing_dir) = path.parent() {
+        std::fs::create_dir_all(containing_dir).map_err(|e| Error::io(path, e))?;
+    }
+    std::fs::write(path, rendered).map_err(|e| Error::io(path, e))
+}
+
+/// Remove a page which no longer has any content, if it was ever written.
+fn remove_html(path: &Path) -> Result<(), Error> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(Error::io(path, e)),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_site::{config, pages};
+
+    fn term_names(terms: &Terms) -> Vec<(String, Vec<String>)> {
+        terms
+            .terms
+            .values()
+            .map(|term| {
+                let slugs = term
+                    .pages
+                    .iter()
+                    .map(|page| page.metadata.slug.clone())
+                    .collect();
+                (term.name.clone(), slugs)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn groups_pages_by_term() {
+        let config = config(
+            r#"taxonomies: [{ name: "tag", key: "tags", type: "multiple", templates: { item: "tag.html" } }]"#,
+        );
+        let pages = pages(
+            &config,
+            &[
+                (
+                    "a.md",
+                    "---\ntitle: A\ndate: 2019-01-01T00:00:00Z\ntags: [Rust, writing]\n---\n",
+                ),
+                (
+                    "b.md",
+                    "---\ntitle: B\ndate: 2020-01-01T00:00:00Z\ntags: [rust]\n---\n",
+                ),
+                ("c.md", "---\ntitle: C\n---\n"),
+            ],
+        );
+
+        let (terms, errors) = assign(&config, &pages);
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(
+            term_names(&terms[0]),
+            vec![
+                (
+                    String::from("Rust"),
+                    vec![String::from("b"), String::from("a")]
+                ),
+                (String::from("writing"), vec![String::from("a")]),
+            ]
+        );
+    }
+
+    #[test]
+    fn custom_keys_and_defaults() {
+        let config = config(
+            r#"taxonomies: [{ name: "category", type: "multiple", default: "misc", templates: { item: "category.html" } }]"#,
+        );
+        let pages = pages(
+            &config,
+            &[
+                ("a.md", "---\ntitle: A\ncategory: tech\n---\n"),
+                ("b.md", "---\ntitle: B\n---\n"),
+            ],
+        );
+
+        let (terms, errors) = assign(&config, &pages);
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(
+            term_names(&terms[0]),
+            vec![
+                (String::from("misc"), vec![String::from("b")]),
+                (String::from("tech"), vec![String::from("a")]),
+            ]
+        );
+    }
+
+    #[test]
+    fn binary_taxonomies() {
+        let config = config(
+            r#"taxonomies: [{ name: "standalone", type: "binary", templates: { item: "standalone.html" } }]"#,
+        );
+        let pages = pages(
+            &config,
+            &[
+                ("a.md", "---\ntitle: A\nstandalone: true\n---\n"),
+                ("b.md", "---\ntitle: B\nstandalone: false\n---\n"),
+                ("c.md", "---\ntitle: C\n---\n"),
+            ],
+        );
+
+        let (terms, errors) = assign(&config, &pages);
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(
+            term_names(&terms[0]),
+            vec![(String::from("standalone"), vec![String::from("a")])]
+        );
+        assert_eq!(terms[0].term_slug("standalone"), "standalone");
+    }
+
+    #[test]
+    fn binary_terms_do_not_overwrite_the_list_page() {
+        let config = config(
+            r#"taxonomies: [{ name: "standalone", type: "binary", templates: { list: "list.html", item: "standalone.html" } }]"#,
+        );
+        let pages = pages(
+            &config,
+            &[("a.md", "---\ntitle: A\nstandalone: true\n---\n")],
+        );
+        let (terms, _) = assign(&config, &pages);
+        let paths = terms[0].output_paths(&config);
+        assert_eq!(
+            paths,
+            vec![
+                config.output.join("standalone.html"),
+                config.output.join("standalone/standalone.html")
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_terms_without_a_slug() {
+        let config = config(
+            r#"taxonomies: [{ name: "tag", key: "tags", type: "multiple", hierarchical: true, templates: { item: "tag.html" } }]"#,
+        );
+        let pages = pages(
+            &config,
+            &[
+                ("empty.md", "---\ntitle: Empty\ntags: [\"!!!\"]\n---\n"),
+                (
+                    "nested.md",
+                    "---\ntitle: Nested\ntags: [{ tech: [\"???\"] }]\n---\n",
+                ),
+                ("fine.md", "---\ntitle: Fine\ntags: [rust]\n---\n"),
+            ],
+        );
+
+        let (terms, errors) = assign(&config, &pages);
+        assert_eq!(errors.len(), 2, "{:?}", errors);
+        for (error, name) in errors.iter().zip(&["!!!", "???"]) {
+            match error {
+                Error::FrontMatter { message, .. } => {
+                    assert!(message.contains(&format!("'{}'", name)), "{}", message)
+                }
+                other => panic!("expected a front matter error, got {:?}", other),
+            }
+        }
+        assert_eq!(
+            term_names(&terms[0]),
+            vec![(String::from("rust"), vec![String::from("fine")])]
+        );
+    }
+
+    #[test]
+    fn reports_required_and_limit_violations() {
+        let config = config(
+            r#"taxonomies: [{ name: "tag", key: "tags", type: "multiple", required: true, limit: 1, templates: { item: "tag.html" } }]"#,
+        );
+        let pages = pages(
+            &config,
+            &[
+                ("ok.md", "---\ntitle: OK\ntags: [one]\n---\n"),
+                ("none.md", "---\ntitle: None\n---\n"),
+                ("many.md", "---\ntitle: Many\ntags: [one, two]\n---\n"),
+            ],
+        );
+
+        let (_, errors) = assign(&config, &pages);
+        let mut errors: Vec<String> = errors.iter().map(ToString::to_string).collect();
+        errors.sort();
+        assert_eq!(
+            errors,
+            vec![
+                "/site/content/many.md: invalid front matter: taxonomy 'tag' allows at most 1 terms, but found 2",
+                "/site/content/none.md: missing required field: tags (for taxonomy 'tag')",
+            ]
+        );
+    }
+
+    #[test]
+    fn nested_terms_belong_to_their_parents() {
+        let config = config(
+            r#"taxonomies: [{ name: "category", type: "multiple", hierarchical: true, templates: { item: "category.html" } }]"#,
+        );
+        let pages = pages(
+            &config,
+            &[
+                (
+                    "a.md",
+                    "---\ntitle: A\ncategory:\n  - tech:\n    - programming languages\n    - agile software development\n---\n",
+                ),
+                ("b.md", "---\ntitle: B\ncategory: [tech, art]\n---\n"),
+            ],
+        );
+
+        let (terms, errors) = assign(&config, &pages);
+        assert!(errors.is_empty(), "{:?}", errors);
+        let terms = &terms[0];
+        assert_eq!(
+            terms.terms.keys().collect::<Vec<_>>(),
+            vec![
+                "art",
+                "tech",
+                "tech/agile-software-development",
+                "tech/programming-languages",
+            ]
+        );
+        assert_eq!(terms.terms["tech"].pages.len(), 2);
+        assert_eq!(
+            terms.terms["tech/programming-languages"].parent.as_deref(),
+            Some("tech")
+        );
+
+        let config = &config;
+        let top_level = terms.terms_context(config, None, false);
+        let names = top_level
+            .iter()
+            .map(|term| (term.name, term.children.iter().map(|c| c.name).collect()))
+            .collect::<Vec<(&str, Vec<&str>)>>();
+        assert_eq!(
+            names,
+            vec![
+                ("art", vec![]),
+                (
+                    "tech",
+                    vec!["agile software development", "programming languages"]
+                ),
+            ]
+        );
+        assert_eq!(
+            top_level[1].children[1].url,
+            "https://example.com/category/tech/programming-languages"
+        );
+    }
+
+    #[test]
+    fn nesting_requires_a_hierarchical_taxonomy() {
+        let config = config(
+            r#"taxonomies: [{ name: "category", type: "multiple", templates: { item: "category.html" } }]"#,
+        );
+        let pages = pages(
+            &config,
+            &[("a.md", "---\ntitle: A\ncategory:\n  - tech: [rust]\n---\n")],
+        );
+
+        let (terms, errors) = assign(&config, &pages);
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(
+            &errors[0],
+            Error::FrontMatter { message, .. }
+                if message == "taxonomy 'category' is not hierarchical, so its terms cannot be nested"
+        ));
+        assert!(terms[0].terms.is_empty());
+    }
+}
@@ -0,0 +1,554 @@
+//! Date-based archives for `temporal` taxonomies: a page for every year, month,
+//! and day which has any content, e.g. `/2019`, `/2019/11`, `/2019/11/30` (or
+//! `/2019/11/` and so on, with pretty permalinks).
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+
+use chrono::{DateTime, Datelike, FixedOffset, NaiveDate};
+use serde_derive::Serialize;
+use tera::Context;
+
+use crate::config::{Config, Taxonomy, TaxonomyKind};
+use crate::error::Error;
+use crate::feed::Feed;
+use crate::layout::{Layouts, PageLink};
+use crate::page::Page;
+
+use super::{remove_html, write_html};
+
+/// All the periods for a single temporal taxonomy, and the pages in them.
+pub(crate) struct Archive<'a> {
+    taxonomy: &'a Taxonomy,
+    /// Every period with any content. Because of the derived ordering, a year
+    /// sorts before all of its months and a month before all of its days.
+    periods: BTreeMap<Period, Vec<&'a Page>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct Period {
+    year: i32,
+    month: Option<u32>,
+    day: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Granularity {
+    Year,
+    Month,
+    Day,
+}
+
+/// Assign every dated page to its year, month, and day in every temporal
+/// taxonomy.
+pub(crate) fn assign<'a>(config: &'a Config, pages: &'a [Page]) -> (Vec<Archive<'a>>, Vec<Error>) {
+    let mut archives = Vec::new();
+    let mut errors = Vec::new();
+
+    for taxonomy in &config.taxonomies {
+        if taxonomy.kind != TaxonomyKind::Temporal {
+            continue;
+        }
+
+        let mut archive = Archive {
+            taxonomy,
+            periods: BTreeMap::new(),
+        };
+
+        for page in pages {
+            match archive_date(taxonomy, page) {
+                Some(date) => {
+                    for period in Period::containing(date.naive_local().date()) {
+                        archive.periods.entry(period).or_default().push(page);
+                    }
+                }
+                None if taxonomy.required => errors.push(Error::MissingField {
+                    path: page.source_path.clone(),
+                    field: format!("date (for taxonomy '{}')", taxonomy.name),
+                }),
+                None => {}
+            }
+        }
+
+        for pages in archive.periods.values_mut() {
+            pages.sort_by(|a, b| archive_date(taxonomy, b).cmp(&archive_date(taxonomy, a)));
+        }
+
+        archives.push(archive);
+    }
+
+    (archives, errors)
+}
+
+/// The date to archive `page` under: its own date if it has one, or if the
+/// taxonomy includes title-only items, the date it was last updated.
+fn archive_date<'p>(taxonomy: &Taxonomy, page: &'p Page) -> Option<&'p DateTime<FixedOffset>> {
+    match page.metadata.date() {
+        Some(date) => Some(date),
+        None if taxonomy.include_title_only => page.metadata.updated.as_ref(),
+        None => None,
+    }
+}
+
+impl Period {
+    fn containing(date: NaiveDate) -> [Period; 3] {
+        let (year, month, day) = (date.year(), date.month(), date.day());
+        [
+            Period {
+                year,
+                month: None,
+                day: None,
+            },
+            Period {
+                year,
+                month: Some(month),
+                day: None,
+            },
+            Period {
+                year,
+                month: Some(month),
+                day: Some(day),
+            },
+        ]
+    }
+
+    fn granularity(&self) -> Granularity {
+        match (self.month, self.day) {
+            (None, _) => Granularity::Year,
+            (Some(_), None) => Granularity::Month,
+            (Some(_), Some(_)) => Granularity::Day,
+        }
+    }
+
+    /// The path to the period, relative to the root of the site, e.g.
+    /// `2019/11`.
+    fn slug(&self) -> String {
+        match (self.month, self.day) {
+            (None, _) => format!("{}", self.year),
+            (Some(month), None) => format!("{}/{:02}", self.year, month),
+            (Some(month), Some(day)) => format!("{}/{:02}/{:02}", self.year, month, day),
+        }
+    }
+
+    /// A human-readable name for the period, e.g. `November 2019`.
+    fn name(&self) -> String {
+        let date =
+            NaiveDate::from_ymd_opt(self.year, self.month.unwrap_or(1), self.day.unwrap_or(1))
+                .expect("periods are only ever constructed from valid dates");
+        match self.granularity() {
+            Granularity::Year => date.format("%Y").to_string(),
+            Granularity::Month => date.format("%B %Y").to_string(),
+            Granularity::Day => date.format("%B %-d, %Y").to_string(),
+        }
+    }
+
+    /// Whether `other` is nested directly within this period, e.g. a month of
+    /// this year.
+    fn is_parent_of(&self, other: &Period) -> bool {
+        match self.granularity() {
+            Granularity::Year => {
+                other.granularity() == Granularity::Month && other.year == self.year
+            }
+            Granularity::Month => {
+                other.granularity() == Granularity::Day
+                    && other.year == self.year
+                    && other.month == self.month
+            }
+            Granularity::Day => false,
+        }
+    }
+}
+
+/// Enough about a period to link to it, along with the periods within it.
+#[derive(Serialize)]
+struct PeriodLink {
+    name: String,
+    url: String,
+    count: usize,
+    periods: Vec<PeriodLink>,
+}
+
+#[derive(Serialize)]
+struct ArchiveContext<'a> {
+    name: &'a str,
+    url: String,
+    /// Every year, with its months and their days nested within it.
+    periods: Vec<PeriodLink>,
+}
+
+#[derive(Serialize)]
+struct PeriodContext<'a> {
+    kind: Granularity,
+    name: String,
+    url: String,
+    year: i32,
+    month: Option<u32>,
+    day: Option<u32>,
+    count: usize,
+    pages: Vec<PageLink<'a>>,
+    /// The months of a year, or the days of a month.
+    periods: Vec<PeriodLink>,
+    /// The closest earlier period of the same kind which has any content.
+    previous: Option<PeriodLink>,
+    /// The closest later period of the same kind which has any content.
+    next: Option<PeriodLink>,
+}
+
+impl<'a> Archive<'a> {
+    fn link(&self, config: &Config, period: &Period, nested: bool) -> PeriodLink {
+        PeriodLink {
+            name: period.name(),
+            url: config.url_for(&period.slug()),
+            count: self.periods[period].len(),
+            periods: if nested {
+                self.periods
+                    .keys()
+                    .filter(|child| period.is_parent_of(child))
+                    .map(|child| self.link(config, child, nested))
+                    .collect()
+            } else {
+                Vec::new()
+            },
+        }
+    }
+
+    /// Every period which includes any of the `changed` pages, along with the
+    /// nearest periods of the same kind on either side.
+    fn affected(&self, changed: &[&Page]) -> BTreeSet<Period> {
+        let mut affected = BTreeSet::new();
+        for period in changed
+            .iter()
+            .filter_map(|page| archive_date(self.taxonomy, page))
+            .flat_map(|date| Period::containing(date.naive_local().date()))
+        {
+            let same_kind = |other: &&Period| other.granularity() == period.granularity();
+            let previous = self
+                .periods
+                .range(..period)
+                .map(|(other, _)| other)
+                .rfind(same_kind);
+            let next = self
+                .periods
+                .range(period..)
+                .map(|(other, _)| other)
+                .filter(|other| **other != period)
+                .find(same_kind);
+
+            affected.extend(previous.into_iter().chain(next));
+            affected.insert(period);
+        }
+        affected
+    }
+
+    fn context(&self, config: &'a Config, period: &Period) -> PeriodContext<'_> {
+        let same_kind = |other: &&Period| other.granularity() == period.granularity();
+        let previous = self
+            .periods
+            .range(..*period)
+            .map(|(other, _)| other)
+            .rfind(same_kind);
+        let next = self
+            .periods
+            .range(*period..)
+            .map(|(other, _)| other)
+            .skip(1)
+            .find(same_kind);
+
+        PeriodContext {
+            kind: period.granularity(),
+            name: period.name(),
+            url: config.url_for(&period.slug()),
+            year: period.year,
+            month: period.month,
+            day: period.day,
+            count: self.periods[period].len(),
+            pages: self.periods[period]
+                .iter()
+                .map(|page| PageLink::new(page, config))
+                .collect(),
+            periods: self
+                .periods
+                .keys()
+                .filter(|child| period.is_parent_of(child))
+                .map(|child| self.link(config, child, false))
+                .collect(),
+            previous: previous.map(|other| self.link(config, other, false)),
+            next: next.map(|other| self.link(config, other, false)),
+        }
+    }
+
+    /// Render the list page (if the taxonomy has one) and every period's page,
+    /// plus a feed for each period if the taxonomy asks for them.
+    ///
+    /// If `changed` is set, render only the periods which include (or included)
+    /// those pages and their neighbors, whose previous and next links may have
+    /// changed, and remove the pages for any such periods which no longer have
+    /// any items.
+    pub(crate) fn write(
+        &self,
+        layouts: &Layouts,
+        config: &'a Config,
+        changed: Option<&[&Page]>,
+    ) -> Result<(), Error> {
+        let affected = changed.map(|changed| self.affected(changed));
+        let is_affected = |period: &Period| match &affected {
+            Some(periods) => periods.contains(period),
+            None => true,
+        };
+        if matches!(&affected, Some(periods) if periods.is_empty()) {
+            return Ok(());
+        }
+
+        for removed in affected
+            .iter()
+            .flatten()
+            .filter(|period| !self.periods.contains_key(period))
+        {
+            remove_html(&period_path(config, removed))?;
+        }
+
+        if let Some(list_template) = &self.taxonomy.templates.list {
+            let mut context = Context::new();
+            context.insert("config", config);
+            context.insert(
+                "taxonomy",
+                &ArchiveContext {
+                    name: &self.taxonomy.name,
+                    url: config.url_for(&slug::slugify(&self.taxonomy.name)),
+                    periods: self
+                        .periods
+                        .keys()
+                        .filter(|period| period.granularity() == Granularity::Year)
+                        .map(|period| self.link(config, period, true))
+                        .collect(),
+                },
+            );
+
+            let path = config.output_path(&slug::slugify(&self.taxonomy.name));
+            let rendered = layouts
+                .render(list_template, &context)
+                .map_err(|e| e.rendering(&path))?;
+            write_html(&path, rendered)?;
+        }
+
+        for period in self.periods.keys().filter(|period| is_affected(period)) {
+            let mut context = Context::new();
+            context.insert("config", config);
+            context.insert("taxonomy", &self.taxonomy.name);
+            context.insert("period", &self.context(config, period));
+
+            let path = period_path(config, period);
+            let rendered = layouts
+                .render(&self.taxonomy.templates.item, &context)
+                .map_err(|e| e.rendering(&path))?;
+            write_html(&path, rendered)?;
+        }
+
+        if self.taxonomy.generate_feeds {
+            for (period, pages) in self
+                .periods
+                .iter()
+                .filter(|(period, _)| is_affected(period))
+            {
+                self.feed(config, period, pages).write(&config.output)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn feed(&self, config: &'a Config, period: &Period, pages: &[&'a Page]) -> Feed<'a> {
+        Feed::new(
+            format!("{}: {}", config.title.normal, period.name()),
+            period.slug(),
+            config.feeds.engines.clone(),
+            config,
+            pages.iter().copied(),
+        )
+    }
+
+    /// Every file [`Archive::write`] writes when writing everything.
+    pub(crate) fn output_paths(&self, config: &'a Config) -> Vec<PathBuf> {
+        let mut paths = Vec::new();
+        if self.taxonomy.templates.list.is_some() {
+            paths.push(config.output_path(&slug::slugify(&self.taxonomy.name)));
+        }
+        for (period, pages) in &self.periods {
+            paths.push(period_path(config, period));
+            if self.taxonomy.generate_feeds {
+                paths.extend(
+                    self.feed(config, period, pages)
+                        .output_paths(&config.output),
+                );
+            }
+        }
+        paths
+    }
+
+    /// What writes the archive's pages, for reporting collisions.
+    pub(crate) fn describe(&self) -> String {
+        format!("the '{}' archive", self.taxonomy.name)
+    }
+}
+
+/// Where the page for the period goes, e.g. `2019/11.html` (or
+/// `2019/11/index.html` with pretty permalinks) for November 2019.
+fn period_path(config: &Config, period: &Period) -> PathBuf {
+    config.output_path(&period.slug())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_site::{config, pages};
+
+    const SOURCES: &[(&str, &str)] = &[
+        (
+            "a.md",
+            "---\ntitle: A\ndate: 2019-11-30T09:00:00-07:00\n---\n",
+        ),
+        ("b.md", "---\ndate: 2019-11-30T22:00:00-07:00\n---\n"),
+        (
+            "c.md",
+            "---\ntitle: C\ndate: 2019-12-02T09:00:00-07:00\n---\n",
+        ),
+        (
+            "d.md",
+            "---\ntitle: D\ndate: 2020-01-15T09:00:00-07:00\n---\n",
+        ),
+        (
+            "about.md",
+            "---\ntitle: About\nupdated: 2020-01-20T09:00:00-07:00\n---\n",
+        ),
+    ];
+
+    fn slugs(archive: &Archive, slug: &str) -> Vec<String> {
+        let (_, pages) = archive
+            .periods
+            .iter()
+            .find(|(period, _)| period.slug() == slug)
+            .unwrap_or_else(|| panic!("no period {}", slug));
+        pages
+            .iter()
+            .map(|page| page.metadata.slug.clone())
+            .collect()
+    }
+
+    #[test]
+    fn groups_by_year_month_and_day() {
+        let config = config(
+            r#"taxonomies: [{ name: "date", type: "temporal", templates: { item: "archives.html" } }]"#,
+        );
+        let pages = pages(&config, SOURCES);
+
+        let (archives, errors) = assign(&config, &pages);
+        assert!(errors.is_empty(), "{:?}", errors);
+        let archive = &archives[0];
+
+        assert_eq!(
+            archive.periods.keys().map(Period::slug).collect::<Vec<_>>(),
+            vec![
+                "2019",
+                "2019/11",
+                "2019/11/30",
+                "2019/12",
+                "2019/12/02",
+                "2020",
+                "2020/01",
+                "2020/01/15",
+            ]
+        );
+        assert_eq!(slugs(archive, "2019"), vec!["c", "b", "a"]);
+        assert_eq!(slugs(archive, "2019/11/30"), vec!["b", "a"]);
+        assert_eq!(slugs(archive, "2020"), vec!["d"]);
+    }
+
+    #[test]
+    fn title_only_items_can_use_updated() {
+        let config = config(
+            r#"taxonomies: [{ name: "date", type: "temporal", include_title_only: true, templates: { item: "archives.html" } }]"#,
+        );
+        let pages = pages(&config, SOURCES);
+
+        let (archives, _) = assign(&config, &pages);
+        assert_eq!(slugs(&archives[0], "2020/01"), vec!["about", "d"]);
+        assert_eq!(slugs(&archives[0], "2020/01/20"), vec!["about"]);
+    }
+
+    #[test]
+    fn links_to_neighboring_periods_of_the_same_kind() {
+        let config = config(
+            r#"taxonomies: [{ name: "date", type: "temporal", templates: { item: "archives.html" } }]"#,
+        );
+        let pages = pages(&config, SOURCES);
+        let (archives, _) = assign(&config, &pages);
+        let archive = &archives[0];
+
+        let november = Period {
+            year: 2019,
+            month: Some(11),
+            day: None,
+        };
+        let context = archive.context(&config, &november);
+        assert_eq!(context.name, "November 2019");
+        assert_eq!(context.url, "https://example.com/2019/11");
+        assert!(context.previous.is_none());
+        assert_eq!(
+            context.next.map(|next| next.name).as_deref(),
+            Some("December 2019")
+        );
+        assert_eq!(
+            context
+                .periods
+                .iter()
+                .map(|period| period.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["November 30, 2019"]
+        );
+
+        let december_2nd = Period {
+            year: 2019,
+            month: Some(12),
+            day: Some(2),
+        };
+        let context = archive.context(&config, &december_2nd);
+        assert_eq!(
+            context.previous.map(|prev| prev.url).as_deref(),
+            Some("https://example.com/2019/11/30")
+        );
+        assert_eq!(
+            context.next.map(|next| next.name).as_deref(),
+            Some("January 15, 2020")
+        );
+    }
+
+    #[test]
+    fn periods_respect_pretty_permalinks() {
+        let november = Period {
+            year: 2019,
+            month: Some(11),
+            day: None,
+        };
+
+        let plain = config("");
+        assert_eq!(
+            period_path(&plain, &november),
+            plain.output.join("2019/11.html")
+        );
+
+        let pretty = config(
+            r#"permalinks: { pretty: true }, taxonomies: [{ name: "date", type: "temporal", templates: { item: "archives.html" } }]"#,
+        );
+        let pages = pages(&pretty, SOURCES);
+        let (archives, _) = assign(&pretty, &pages);
+        assert_eq!(
+            archives[0].context(&pretty, &november).url,
+            "https://example.com/2019/11/"
+        );
+        assert_eq!(
+            period_path(&pretty, &november),
+            pretty.output.join("2019/11/index.html")
+        );
+    }
+}
@@ -0,0 +1,102 @@
+//! Sites for tests: [`config`] and [`pages`] make one in memory, for testing
+//! what lx does with a site's content, and [`TestSite`] makes one on disk, for
+//! testing what it does with the files.
+
+use std::path::{Path, PathBuf};
+
+use syntect::parsing::SyntaxSet;
+
+use crate::config::Config;
+use crate::page::{metadata::cascade::Cascade, Page, Source};
+
+/// A site config, with `overrides` (JSON5 members, e.g. `taxonomies: [...]`)
+/// added to the required settings.
+pub(crate) fn config(overrides: &str) -> Config {
+    json5::from_str(&format!(
+        r#"{{
+            url: "https://example.com/",
+            repo: "https://github.com/example/example.com",
+            title: {{ normal: "Example", stylized: "Example" }},
+            subtitle: "An example",
+            description: "Examples & <such>",
+            author: {{ name: "Alex Example", email: "alex@example.com", links: [] }},
+            output: "output",
+            {}
+        }}"#,
+        overrides
+    ))
+    .expect("test config is valid")
+}
+
+/// A page for each of the `(path, contents)` sources, as if from the content
+/// directory of a site at `/site`.
+pub(crate) fn pages(config: &Config, sources: &[(&str, &str)]) -> Vec<Page> {
+    let syntax_set = SyntaxSet::load_defaults_newlines();
+    let root_dir = Path::new("/site/content");
+    sources
+        .iter()
+        .map(|(path, contents)| {
+            let source = Source {
+                path: root_dir.join(path),
+                contents: String::from(*contents),
+            };
+            Page::new(&source, root_dir, &syntax_set, config, &Cascade::default())
+                .expect("test page is valid")
+        })
+        .collect()
+}
+
+/// A directory for a test, removed again when it is dropped.
+pub(crate) struct TestSite {
+    pub(crate) root: PathBuf,
+}
+
+impl TestSite {
+    /// A site from `lx init`, with `overrides` (JSON5 members, as for
+    /// [`config`]) added to the top of its config. `name` must be unique among
+    /// the tests.
+    pub(crate) fn new(name: &str, overrides: &str) -> TestSite {
+        let site = TestSite::empty(name);
+        crate::initializer::init(site.root.clone(), false).expect("initializes a new site");
+        if !overrides.is_empty() {
+            let config_path = site.config_path();
+            let config = std::fs::read_to_string(&config_path).unwrap();
+            let config = config.replacen("{", &format!("{{\n  {},", overrides), 1);
+            std::fs::write(&config_path, config).unwrap();
+        }
+        site
+    }
+
+    /// An empty directory, for tests which need somewhere to write rather than
+    /// a whole site.
+    pub(crate) fn empty(name: &str) -> TestSite {
+        let root = std::env::temp_dir().join(format!("lx-test-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).expect("creates the test directory");
+        TestSite { root }
+    }
+
+    /// Write each of the `(path, contents)` files, relative to the root.
+    pub(crate) fn write(&self, files: &[(&str, &str)]) -> &TestSite {
+        for (path, contents) in files {
+            let path = self.root.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        }
+        self
+    }
+
+    pub(crate) fn config_path(&self) -> PathBuf {
+        self.root.join("_data/config.json5")
+    }
+
+    pub(crate) fn config(&self) -> Config {
+        Config::from_file(&self.config_path()).expect("loads the config")
+    }
+}
+
+impl Drop for TestSite {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.root);
+    }
+}
@@ -0,0 +1,196 @@
+//! The syntax highlighting stylesheets: CSS for the light and dark themes named
+//! in the site config, for the classes lx puts on highlighted code.
+//!
+//! Generating them means loading every theme, so rather than doing that on
+//! every build, `lx themes` writes them into the site (to `themes.stylesheets`)
+//! to be checked in. Builds use those files as they are whenever both exist,
+//! and only generate the stylesheets otherwise. Either way, they end up at
+//! `light.css` and `dark.css` in the output, as assets (see [`crate::assets`]).
+//!
+//! Besides syntect's default themes, any `.tmTheme` files in `themes.directory`
+//! are available, by their file names, e.g. `_themes/Solarized.tmTheme` as
+//! `Solarized`.
+
+use std::path::{Path, PathBuf};
+
+use syntect::highlighting::ThemeSet;
+use syntect::html::{css_for_theme_with_class_style, ClassStyle};
+
+use crate::build::files_under;
+use crate::config::Config;
+use crate::error::Error;
+
+/// The stylesheets' names, in the output and in `themes.stylesheets`.
+const LIGHT: &str = "light.css";
+const DARK: &str = "dark.css";
+
+/// The name of every theme available to the site in `site_directory`.
+pub fn list(site_directory: PathBuf) -> Result<Vec<String>, Error> {
+    let (_, config) = load_config(&site_directory)?;
+    let ThemeSet { themes } = load(&config)?;
+    Ok(themes.into_keys().collect())
+}
+
+/// Write the stylesheets for the site in `site_directory`, returning the paths
+/// written.
+pub fn generate(site_directory: PathBuf) -> Result<Vec<PathBuf>, Error> {
+    let (root, config) = load_config(&site_directory)?;
+    let dir = &config.themes.stylesheets;
+    std::fs::create_dir_all(dir).map_err(|e| Error::io(dir, e))?;
+
+    let mut written = Vec::new();
+    for (name, css) in css(&root, &config)? {
+        let path = dir.join(name);
+        std::fs::write(&path, css).map_err(|e| Error::io(&path, e))?;
+        written.push(path);
+    }
+    Ok(written)
+}
+
+/// The stylesheets for the site at `site_root`, by file name: the ones `lx
+/// themes` wrote, if it has, and otherwise freshly generated.
+pub(crate) fn stylesheets(
+    site_root: &Path,
+    config: &Config,
+) -> Result<Vec<(String, String)>, Error> {
+    let dir = &config.themes.stylesheets;
+    let pregenerated = [LIGHT, DARK].iter().map(|name| dir.join(name));
+    if pregenerated.clone().all(|path| path.is_file()) {
+        return [LIGHT, DARK]
+            .iter()
+            .zip(pregenerated)
+            .map(|(name, path)| {
+                std::fs::read_to_string(&path)
+                    .map(|css| (name.to_string(), css))
+                    .map_err(|e| Error::io(&path, e))
+            })
+            .collect();
+    }
+
+    css(site_root, config)
+}
+
+fn load_config(site_directory: &Path) -> Result<(PathBuf, Config), Error> {
+    let root = std::fs::canonicalize(site_directory).map_err(|e| Error::io(site_directory, e))?;
+    let config = Config::from_file(&root.join("_data/config.json5"))?;
+    Ok((root, config))
+}
+
+/// Syntect's default themes, plus the site's own.
+fn load(config: &Config) -> Result<ThemeSet, Error> {
+    let mut theme_set = ThemeSet::load_defaults();
+    for path in files_under(&config.themes.directory, "**/*.tmTheme")? {
+        let theme = ThemeSet::get_theme(&path).map_err(|e| Error::Theme {
+            path: path.clone(),
+            message: e.to_string(),
+        })?;
+        if let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) {
+            theme_set.themes.insert(String::from(name), theme);
+        }
+    }
+    Ok(theme_set)
+}
+
+fn css(site_root: &Path, config: &Config) -> Result<Vec<(String, String)>, Error> {
+    let ThemeSet { themes } = load(config)?;
+    let style = ClassStyle::Spaced;
+
+    [(LIGHT, &config.themes.light), (DARK, &config.themes.dark)]
+        .iter()
+        .map(
+            |(file_name, theme_name)| match themes.get(theme_name.as_str()) {
+                Some(theme) => Ok((
+                    file_name.to_string(),
+                    css_for_theme_with_class_style(theme, style),
+                )),
+                None => Err(Error::Config {
+                    path: site_root.join("_data/config.json5"),
+                    location: None,
+                    message: format!(
+                        "there is no theme '{}' (see `lx themes --list`)",
+                        theme_name
+                    ),
+                }),
+            },
+        )
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_site::TestSite;
+
+    const THEME: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+  <key>name</key>
+  <string>Plain</string>
+  <key>settings</key>
+  <array>
+    <dict>
+      <key>settings</key>
+      <dict>
+        <key>background</key>
+        <string>#FFFFFF</string>
+        <key>foreground</key>
+        <string>#111111</string>
+      </dict>
+    </dict>
+  </array>
+</dict>
+</plist>
+"#;
+
+    #[test]
+    fn lists_default_and_site_themes() {
+        let site = TestSite::new("themes-list", "themes: { light: \"Plain\" }");
+        site.write(&[("_themes/Plain.tmTheme", THEME)]);
+
+        let themes = list(site.root.clone()).expect("lists the themes");
+        assert!(themes.contains(&String::from("Plain")));
+        assert!(themes.contains(&String::from("base16-ocean.dark")));
+
+        site.write(&[("_themes/nested/Broken.tmTheme", "<plist>")]);
+        match list(site.root.clone()) {
+            Err(Error::Theme { path, .. }) => {
+                assert!(path.ends_with("_themes/nested/Broken.tmTheme"))
+            }
+            other => panic!("expected a theme error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn builds_use_the_pregenerated_stylesheets() {
+        let site = TestSite::new("themes-generate", "themes: { light: \"Plain\" }");
+        site.write(&[("_themes/Plain.tmTheme", THEME)]);
+        let (root, mut config) = load_config(&site.root).unwrap();
+
+        let written = generate(site.root.clone()).expect("writes the stylesheets");
+        assert_eq!(
+            written,
+            vec![
+                root.join("_themes/light.css"),
+                root.join("_themes/dark.css")
+            ]
+        );
+        let light = std::fs::read_to_string(&written[0]).unwrap();
+        assert!(light.contains("color: #111111"), "{}", light);
+
+        std::fs::write(&written[1], "/* checked in */").unwrap();
+        let css = stylesheets(&root, &config).expect("loads the stylesheets");
+        assert_eq!(
+            css[1],
+            (String::from("dark.css"), String::from("/* checked in */"))
+        );
+
+        // Without both, they are generated, which fails for a missing theme.
+        std::fs::remove_file(&written[0]).unwrap();
+        config.themes.light = String::from("Nope");
+        match stylesheets(&root, &config) {
+            Err(Error::Config { message, .. }) => assert!(message.contains("'Nope'")),
+            other => panic!("expected a missing theme, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,464 @@
+//! Watch a site's sources and rebuild whenever they change, redoing only the
+//! work for whatever changed: re-parsing only the content which changed, and
+//! rewriting only the pages, feeds, taxonomy pages, and archive pages which
+//! include it.
+
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::Duration;
+
+use notify::{RecursiveMode, Watcher};
+
+use crate::build::{Mode, Site};
+use crate::error::{Error, Report};
+use crate::layout::Layouts;
+use crate::page::metadata::cascade::Cascade;
+use crate::page::Page;
+use crate::redirects::{self, Rules};
+use crate::styles;
+
+/// How long to wait for a burst of file system events (e.g. from an editor
+/// saving several files at once) to finish before rebuilding.
+const SETTLE_TIME: Duration = Duration::from_millis(100);
+
+/// Build the site in `site_directory`, then rebuild it whenever it changes.
+/// Runs until killed.
+pub fn watch(site_directory: PathBuf, use_cache: bool) -> Result<(), Error> {
+    run(site_directory, use_cache, || println!("rebuilt"))
+}
+
+/// Build the site, then rebuild it whenever it changes, calling `on_rebuild`
+/// after every build (including the first). Errors in the content do not stop
+/// the watcher: they are reported, and the next change gets another try. That
+/// includes output files which more than one item would write: unlike a one-off
+/// build, a rebuild still writes them, so that fixing one of the items only has
+/// to rewrite that item.
+pub(crate) fn run(
+    site_directory: PathBuf,
+    use_cache: bool,
+    mut on_rebuild: impl FnMut(),
+) -> Result<(), Error> {
+    let (mut site, errors) = Site::load(site_directory, use_cache, Mode::Development)?;
+    report(
+        errors
+            .into_iter()
+            .chain(site.collisions())
+            .chain(site.write_all())
+            .collect(),
+    );
+    on_rebuild();
+
+    let watch_error = |path: &Path, e: notify::Error| Error::Watch {
+        path: path.to_owned(),
+        message: e.to_string(),
+    };
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher =
+        notify::recommended_watcher(sender).map_err(|e| watch_error(&site.root, e))?;
+    watcher
+        .watch(&site.root, RecursiveMode::Recursive)
+        .map_err(|e| watch_error(&site.root, e))?;
+    // The layouts may live outside the site root.
+    let layouts_dir = &site.config.layouts.directory;
+    if !layouts_dir.starts_with(&site.root) {
+        watcher
+            .watch(layouts_dir, RecursiveMode::Recursive)
+            .map_err(|e| watch_error(layouts_dir, e))?;
+    }
+
+    while let Ok(event) = receiver.recv() {
+        let mut paths = BTreeSet::new();
+        collect_paths(event, &mut paths);
+        while let Ok(event) = receiver.recv_timeout(SETTLE_TIME) {
+            collect_paths(event, &mut paths);
+        }
+
+        let changes = Changes::classify(&site, paths);
+        if !changes.is_empty() {
+            report(rebuild(&mut site, changes));
+            on_rebuild();
+        }
+    }
+
+    Ok(())
+}
+
+fn collect_paths(event: notify::Result<notify::Event>, paths: &mut BTreeSet<PathBuf>) {
+    if let Ok(event) = event {
+        if !event.kind.is_access() {
+            paths.extend(event.paths);
+        }
+    }
+}
+
+fn report(errors: Vec<Error>) {
+    if !errors.is_empty() {
+        eprintln!("{}", Report::new(errors));
+    }
+}
+
+/// What needs rebuilding after a set of files changed.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Changes {
+    /// The site config changed, so everything must be rebuilt.
+    config: bool,
+    /// A layout changed, so every page must be re-rendered (but not re-parsed).
+    layouts: bool,
+    /// The directories whose `config.lx.yaml` changed, so all the content
+    /// beneath them must be re-parsed.
+    cascade: BTreeSet<PathBuf>,
+    /// Content files which were added, changed, or removed.
+    content: BTreeSet<PathBuf>,
+    /// The redirects file changed.
+    redirects: bool,
+    /// A file to copy into the output as-is was added, changed, or removed.
+    assets: bool,
+}
+
+impl Changes {
+    /// Sort changed `paths` by what they require rebuilding. Changes to the
+    /// output (which may be inside the site) and anything else lx does not read
+    /// are ignored.
+    pub(crate) fn classify(site: &Site, paths: BTreeSet<PathBuf>) -> Changes {
+        let config_path = site.root.join("_data/config.json5");
+        let content_dir = site.content_dir();
+        let redirects_path = site.root.join(redirects::FILE_NAME);
+        let copy_dirs: Vec<PathBuf> = site
+            .config
+            .other_content
+            .copy
+            .iter()
+            .map(|dir| site.root.join(dir))
+            .collect();
+
+        let styles_dir = site.config.styles.directory.as_ref();
+        let themes = &site.config.themes;
+
+        let mut changes = Changes::default();
+        for path in paths {
+            if path.starts_with(&site.config.output) {
+                continue;
+            }
+
+            // Every page may highlight code with the syntax definitions, so
+            // changing them means starting over, just as for the config.
+            if path == config_path || path.starts_with(&site.config.syntaxes.directory) {
+                changes.config = true;
+            } else if path == redirects_path {
+                changes.redirects = true;
+            } else if styles_dir.is_some_and(|dir| {
+                path.starts_with(dir)
+                    || (styles::is_source(&path)
+                        && !path.starts_with(&content_dir)
+                        && !path.starts_with(&site.config.layouts.directory))
+            }) {
+                // The compiled stylesheets are assets, and they may use any
+                // file in their directory, or any Sass file they can load from
+                // the site root.
+                changes.assets = true;
+            } else if path.starts_with(&themes.directory) || path.starts_with(&themes.stylesheets) {
+                // So are the syntax highlighting stylesheets.
+                changes.assets = true;
+            } else if path.starts_with(&site.config.layouts.directory) {
+                changes.layouts = true;
+            } else if path.starts_with(&content_dir) {
+                match path.file_name().and_then(|name| name.to_str()) {
+                    Some("config.lx.yaml") => {
+                        if let Some(dir) = path.parent() {
+                            changes.cascade.insert(dir.to_owned());
+                        }
+                    }
+                    _ if path.extension() == Some("md".as_ref()) => {
+                        changes.content.insert(path);
+                    }
+                    // A directory which was added, removed, or renamed: every
+                    // item in it (before or after) changed, and so may any
+                    // assets in it. (Or a file without an extension.)
+                    _ if path.extension().is_none() => {
+                        changes.content.extend(content_under(site, &path));
+                        changes.assets = true;
+                    }
+                    _ => changes.assets = true,
+                }
+            } else if copy_dirs.iter().any(|dir| path.starts_with(dir)) {
+                changes.assets = true;
+            }
+        }
+
+        changes
+    }
+
+    fn is_empty(&self) -> bool {
+        !self.config
+            && !self.layouts
+            && self.cascade.is_empty()
+            && self.content.is_empty()
+            && !self.redirects
+            && !self.assets
+    }
+}
+
+/// Every content file under `dir`, whether it is on disk now or was when the
+/// site was last built. (Listing the files only fails for a content directory
+/// which cannot be globbed, and loading the site would have failed first.)
+fn content_under(site: &Site, dir: &Path) -> Vec<PathBuf> {
+    site.files()
+        .map(|files| files.content)
+        .unwrap_or_default()
+        .into_iter()
+        .chain(site.pages.iter().map(|page| page.source_path.clone()))
+        .filter(|path| path.starts_with(dir))
+        .collect()
+}
+
+/// Apply `changes` to the site, and write out whatever they affect.
+pub(crate) fn rebuild(site: &mut Site, changes: Changes) -> Vec<Error> {
+    if changes.config {
+        return match Site::load(site.root.clone(), site.cache.is_some(), site.mode) {
+            Ok((new_site, errors)) => {
+                *site = new_site;
+                errors
+                    .into_iter()
+                    .chain(site.collisions())
+                    .chain(site.write_all())
+                    .collect()
+            }
+            Err(e) => vec![e],
+        };
+    }
+
+    if changes.layouts {
+        match Layouts::load(&site.config.layouts.directory) {
+            Ok(mut layouts) => {
+                layouts.register_assets(site.assets.manifest());
+                site.layouts = layouts;
+            }
+            Err(e) => return vec![e],
+        }
+    }
+
+    let mut errors = Vec::new();
+    if changes.redirects {
+        match Rules::load(&site.root) {
+            Ok((redirects, redirect_errors)) => {
+                site.redirects = redirects;
+                errors.extend(redirect_errors);
+            }
+            Err(e) => return vec![e],
+        }
+    }
+
+    if changes.assets {
+        let old: Vec<PathBuf> = site
+            .assets
+            .files()
+            .iter()
+            .map(|asset| asset.output.clone())
+            .collect();
+        errors.extend(site.reload_assets());
+        // Including the old versions of fingerprinted assets which changed.
+        for removed in old
+            .iter()
+            .filter(|old| !site.assets.files().iter().any(|new| &&new.output == old))
+        {
+            if let Err(e) = std::fs::remove_file(removed) {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    errors.push(Error::io(removed, e));
+                }
+            }
+        }
+        errors.extend(site.assets.write(&site.config.output));
+    }
+
+    let mut content = changes.content;
+    if !changes.cascade.is_empty() {
+        let configs = match site.files() {
+            Ok(files) => files.configs,
+            Err(e) => return vec![e],
+        };
+        match Cascade::load(&configs, &site.config.cascade) {
+            Ok(cascade) => site.cascade = cascade,
+            Err(e) => return vec![e],
+        }
+        for dir in &changes.cascade {
+            content.extend(content_under(site, dir));
+        }
+    }
+
+    let (old, kept): (Vec<Page>, Vec<Page>) = std::mem::take(&mut site.pages)
+        .into_iter()
+        .partition(|page| content.contains(&page.source_path));
+    site.pages = kept;
+
+    // Parsing the content again uses the cache entries for it, if there are
+    // any, and the rest (including any for content which is gone) can go.
+    if let Some(cache) = &site.cache {
+        for path in &content {
+            cache.forget(path);
+        }
+    }
+    let (new, parse_errors) =
+        site.parse(content.into_iter().filter(|path| path.is_file()).collect());
+    errors.extend(parse_errors);
+    if let Some(cache) = &site.cache {
+        cache.prune();
+    }
+
+    // Anything which is gone or has moved should not linger in the output.
+    for page in old.iter().filter(|page| {
+        !new.iter()
+            .any(|new| new.metadata.slug == page.metadata.slug)
+    }) {
+        let path = page.output_path(&site.config);
+        if let Err(e) = std::fs::remove_file(&path) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                errors.push(Error::io(&path, e));
+            }
+        }
+    }
+
+    let first_new = site.pages.len();
+    site.pages.extend(new);
+
+    errors.extend(site.collisions());
+    // Fingerprinted assets which changed have new paths, so every page which
+    // links to them has to link to the new ones.
+    if changes.layouts || (changes.assets && site.config.other_content.fingerprint) {
+        errors.extend(site.write_all());
+    } else {
+        let new: Vec<&Page> = site.pages[first_new..].iter().collect();
+        errors.extend(site.write_pages(&new));
+
+        let changed: Vec<&Page> = old.iter().chain(new).collect();
+        if !changed.is_empty() {
+            errors.extend(site.write_collections(Some(&changed)));
+        }
+        // Which pages exist decides whether the redirects are valid.
+        errors.extend(site.write_redirects());
+    }
+
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_site::TestSite;
+
+    /// Load and build the test site, as `lx watch` does when it starts.
+    fn built(test_site: &TestSite) -> Site {
+        let (site, errors) =
+            Site::load(test_site.root.clone(), false, Mode::Development).expect("loads the site");
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert!(site.write_all().is_empty());
+        site
+    }
+
+    fn paths(paths: &[PathBuf]) -> BTreeSet<PathBuf> {
+        paths.iter().cloned().collect()
+    }
+
+    #[test]
+    fn classifies_changes() {
+        let test_site = TestSite::new("watch-classify", "");
+        let site = built(&test_site);
+        let root = site.root.clone();
+
+        let changes = Changes::classify(
+            &site,
+            paths(&[
+                root.join("content/hello-world.md"),
+                root.join("content/journal/config.lx.yaml"),
+                root.join("content/journal/image.png"),
+                root.join("_layouts/base.html"),
+                root.join("_data/archetypes/notes.md"),
+                site.config.output.join("hello-world.html"),
+            ]),
+        );
+        assert_eq!(
+            changes,
+            Changes {
+                config: false,
+                layouts: true,
+                cascade: paths(&[root.join("content/journal")]),
+                content: paths(&[root.join("content/hello-world.md")]),
+                redirects: false,
+                assets: true,
+            }
+        );
+
+        let changes = Changes::classify(&site, paths(&[root.join("_data/config.json5")]));
+        assert!(changes.config);
+
+        let changes = Changes::classify(&site, paths(&[root.join("_redirects")]));
+        assert!(changes.redirects);
+    }
+
+    #[test]
+    fn classifies_stylesheet_changes() {
+        let test_site = TestSite::new("watch-styles", "styles: { directory: \"_styles\" }");
+        let site = built(&test_site);
+        let root = site.root.clone();
+        let classify = |path: &str| Changes::classify(&site, paths(&[root.join(path)]));
+
+        assert!(classify("_styles/main.scss").assets);
+        assert!(classify("_styles/fonts.css").assets);
+        assert!(classify("_shared/_colors.scss").assets);
+        assert!(classify("_themes/Plain.tmTheme").assets);
+        assert!(classify("_themes/light.css").assets);
+
+        let layouts = classify("_layouts/_inline.scss");
+        assert!(layouts.layouts && !layouts.assets);
+    }
+
+    #[test]
+    fn rebuilds_only_what_changed() {
+        let test_site = TestSite::new("watch-rebuild", "");
+        let mut site = built(&test_site);
+        let root = site.root.clone();
+        let output = site.config.output.clone();
+        let feed = || std::fs::read_to_string(output.join("feed.json")).expect("has a feed");
+
+        // Adding an item writes it and updates the feed.
+        let new_post = root.join("content/journal/second.md");
+        std::fs::create_dir_all(new_post.parent().unwrap()).unwrap();
+        std::fs::write(
+            &new_post,
+            "---\ntitle: Second\ndate: 2020-01-02T10:30:00-07:00\n---\nAgain!",
+        )
+        .unwrap();
+        let changes = Changes::classify(&site, paths(std::slice::from_ref(&new_post)));
+        assert!(rebuild(&mut site, changes).is_empty());
+        assert!(output.join("journal/second.html").exists());
+        assert!(feed().contains("Second"));
+        assert_eq!(site.pages.len(), 2);
+
+        // Changing it re-renders it without touching anything else.
+        let untouched = std::fs::metadata(output.join("hello-world.html"))
+            .and_then(|metadata| metadata.modified())
+            .unwrap();
+        std::fs::write(
+            &new_post,
+            "---\ntitle: Second, Revised\ndate: 2020-01-02T10:30:00-07:00\n---\nAgain!",
+        )
+        .unwrap();
+        let changes = Changes::classify(&site, paths(std::slice::from_ref(&new_post)));
+        assert!(rebuild(&mut site, changes).is_empty());
+        let rendered = std::fs::read_to_string(output.join("journal/second.html")).unwrap();
+        assert!(rendered.contains("Second, Revised"));
+        assert!(feed().contains("Second, Revised"));
+        let still_untouched = std::fs::metadata(output.join("hello-world.html"))
+            .and_then(|metadata| metadata.modified())
+            .unwrap();
+        assert_eq!(untouched, still_untouched);
+
+        // Removing it removes its output and drops it from the feed.
+        std::fs::remove_file(&new_post).unwrap();
+        let changes = Changes::classify(&site, paths(&[new_post]));
+        assert!(rebuild(&mut site, changes).is_empty());
+        assert!(!output.join("journal/second.html").exists());
+        assert!(!feed().contains("Second"));
+        assert_eq!(site.pages.len(), 1);
+    }
+}